@@ -0,0 +1,65 @@
+use std::time::{Duration, Instant};
+use python_core_rust_native::parser::tokenizer::*;
+
+// Throughput of tokenizing over a copied character buffer and over borrowed text. Run with
+// 'cargo bench', which tokenizes a generated module of about 8 MB a number of times with each.
+
+const MODULE_PART: &str = r#"
+class Point(NamedTuple):
+    """A point in the plane, with coordinates as floats."""
+    x: float = 0.0
+    y: float = 0.0
+
+    def distance(self, other: 'Point') -> float:
+        # Euclidean distance between points
+        return ((self.x - other.x) ** 2 + (self.y - other.y) ** 2) ** 0.5
+
+    def __repr__(self):
+        return f"Point(x={self.x!r}, y={self.y:.3f})"
+
+def parse(values, *, strict=False):
+    result = [ Point(float(a), float(b)) for a, b in zip(values[::2], values[1::2]) if a != 'ø' ]
+    if strict and len(result) * 2 != len(values):
+        raise ValueError("odd number of coordinates: %d" % len(values))
+    return result
+"#;
+
+const ITERATIONS: u32 = 10;
+
+fn report(name: &str, bytes: usize, tokens: usize, elapsed: Duration) {
+    let seconds = elapsed.as_secs_f64() / ITERATIONS as f64;
+    println!("{:<24} {:>10} tokens {:>10.1} MB/s {:>10.1} Mtokens/s", name, tokens, bytes as f64 / seconds / 1e6, tokens as f64 / seconds / 1e6);
+}
+
+fn main() {
+    let source = MODULE_PART.repeat(8 * 1024 * 1024 / MODULE_PART.len());
+
+    let mut tokens = 0;
+    let start = Instant::now();
+    for _ in 0 .. ITERATIONS {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(source.clone(), 4);
+        tokens = lexer.tokenize().unwrap().len();
+    }
+    report("char buffer", source.len(), tokens, start.elapsed());
+
+    let start = Instant::now();
+    for _ in 0 .. ITERATIONS {
+        let lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(source.clone(), 4);
+        tokens = lexer.into_iter().count();
+    }
+    report("char buffer, streamed", source.len(), tokens, start.elapsed());
+
+    let start = Instant::now();
+    for _ in 0 .. ITERATIONS {
+        let mut lexer = BorrowedTokenizer::borrowing(&source, 4);
+        tokens = lexer.tokenize().unwrap().len();
+    }
+    report("borrowed", source.len(), tokens, start.elapsed());
+
+    let start = Instant::now();
+    for _ in 0 .. ITERATIONS {
+        let lexer = BorrowedTokenizer::borrowing(&source, 4);
+        tokens = lexer.into_iter().count();
+    }
+    report("borrowed, streamed", source.len(), tokens, start.elapsed());
+}
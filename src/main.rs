@@ -1,6 +1,4 @@
-use crate::parser::tokenizer::*;
-
-pub mod parser;
+use python_core_rust_native::parser::tokenizer::*;
 
 fn main() {
     println!("Hello, world!");
@@ -1,2 +1,11 @@
 pub mod tokenizer;
-pub mod token;
\ No newline at end of file
+pub mod token;
+pub mod location;
+pub mod encoding;
+pub mod error;
+pub mod source;
+pub mod literal;
+pub mod version;
+
+#[cfg(feature = "serde")]
+pub mod serialize;
@@ -0,0 +1,187 @@
+// Source encoding detection ///////////////////////////////////////////////////////////////////////
+
+const UTF8_BOM: &[u8] = &[ 0xef, 0xbb, 0xbf ];
+
+// Characters for bytes 0x80 to 0x9f in cp1252, with '\0' for the five bytes left undefined.
+const CP1252_HIGH: [char; 32] = [
+    '\u{20ac}', '\0', '\u{201a}', '\u{0192}', '\u{201e}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{02c6}', '\u{2030}', '\u{0160}', '\u{2039}', '\u{0152}', '\0', '\u{017d}', '\0',
+    '\0', '\u{2018}', '\u{2019}', '\u{201c}', '\u{201d}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{02dc}', '\u{2122}', '\u{0161}', '\u{203a}', '\u{0153}', '\0', '\u{017e}', '\u{0178}'
+];
+
+// Encodings that source files can be declared with.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SourceEncoding {
+    Utf8,
+    Latin1,
+    Ascii,
+    Cp1252
+}
+
+// Finds encoding of source like detect_encoding in the tokenize module of CPython. A UTF-8 BOM gives
+// UTF-8, and a coding cookie is looked for on the first line, and on the second line only when the
+// first line is blank or a comment. Returns encoding and number of bytes of BOM to skip.
+pub fn detect_encoding(source: &[u8]) -> Result<(SourceEncoding, usize), String> {
+    let bom_found = source.starts_with(UTF8_BOM);
+    let skip = if bom_found { UTF8_BOM.len() } else { 0 };
+    let mut lines = source[skip ..].split_inclusive(|b| *b == b'\n');
+
+    let mut cookie = None;
+    if let Some(first) = lines.next() {
+        cookie = find_cookie(first);
+        if cookie.is_none() && is_blank_or_comment(first) {
+            cookie = lines.next().and_then(find_cookie)
+        }
+    }
+
+    match cookie {
+        None => Ok(( SourceEncoding::Utf8, skip )),
+        Some(name) => {
+            let encoding = match normalize_encoding_name(&name).as_str() {
+                "utf-8" | "utf8" => SourceEncoding::Utf8,
+                "iso-8859-1" | "latin-1" | "latin1" | "l1" | "iso8859-1" => SourceEncoding::Latin1,
+                "ascii" | "us-ascii" | "646" => SourceEncoding::Ascii,
+                "cp1252" | "windows-1252" => SourceEncoding::Cp1252,
+                _ => return Err(format!("unknown encoding: {}", name))
+            };
+            if bom_found && encoding != SourceEncoding::Utf8 {
+                return Err(format!("encoding problem: {} with BOM", name))
+            }
+            Ok(( encoding, skip ))
+        }
+    }
+}
+
+// Detects encoding and decodes source into text, with any UTF-8 BOM removed.
+pub fn decode_source(source: &[u8]) -> Result<String, String> {
+    let ( encoding, skip ) = detect_encoding(source)?;
+    let bytes = &source[skip ..];
+    match encoding {
+        SourceEncoding::Utf8 => match std::str::from_utf8(bytes) {
+            Ok(text) => Ok(text.to_string()),
+            Err(error) => {
+                let position = error.valid_up_to();
+                let reason = match ( error.error_len(), bytes[position] ) {
+                    ( None, _ ) => "unexpected end of data",
+                    ( _, 0x80 ..= 0xc1 | 0xf5 ..= 0xff ) => "invalid start byte",
+                    _ => "invalid continuation byte"
+                };
+                Err(format!("'utf-8' codec can't decode byte 0x{:02x} in position {}: {}", bytes[position], position + skip, reason))
+            }
+        },
+        SourceEncoding::Latin1 => Ok(bytes.iter().map(|b| *b as char).collect()),
+        SourceEncoding::Ascii => {
+            match bytes.iter().position(|b| *b >= 0x80) {
+                Some(position) => Err(format!("'ascii' codec can't decode byte 0x{:02x} in position {}: ordinal not in range(128)", bytes[position], position)),
+                None => Ok(bytes.iter().map(|b| *b as char).collect())
+            }
+        },
+        SourceEncoding::Cp1252 => {
+            let mut text = String::with_capacity(bytes.len());
+            for ( position, b ) in bytes.iter().enumerate() {
+                let c = match b {
+                    0x80 ..= 0x9f => CP1252_HIGH[( b - 0x80 ) as usize],
+                    _ => *b as char
+                };
+                if c == '\0' && *b != 0 {
+                    return Err(format!("'charmap' codec can't decode byte 0x{:02x} in position {}: character maps to <undefined>", b, position))
+                }
+                text.push(c)
+            }
+            Ok(text)
+        }
+    }
+}
+
+// Matches '^[ \t\f]*#.*?coding[:=][ \t]*([-\w.]+)' on line and returns encoding name.
+fn find_cookie(line: &[u8]) -> Option<String> {
+    let indent = line.iter().take_while(|b| matches!(b, b' ' | b'\t' | b'\x0c')).count();
+    if line.get(indent) != Some(&b'#') {
+        return None
+    }
+    let mut index = indent + 1;
+    while index + 6 < line.len() {
+        if &line[index .. index + 6] == b"coding" && matches!(line[index + 6], b':' | b'=') {
+            let mut start = index + 7;
+            while matches!(line.get(start), Some(b' ' | b'\t')) {
+                start += 1
+            }
+            let length = line[start ..].iter().take_while(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.')).count();
+            if length > 0 {
+                return Some(String::from_utf8_lossy(&line[start .. start + length]).into_owned())
+            }
+        }
+        index += 1
+    }
+    None
+}
+
+fn is_blank_or_comment(line: &[u8]) -> bool {
+    let indent = line.iter().take_while(|b| matches!(b, b' ' | b'\t' | b'\x0c')).count();
+    matches!(line.get(indent), None | Some(b'#' | b'\r' | b'\n'))
+}
+
+// Lower case with underscores as dashes, and utf-8 or latin-1 with a suffix such as '-unix' trimmed.
+fn normalize_encoding_name(name: &str) -> String {
+    let name = name.to_ascii_lowercase().replace('_', "-");
+    for prefix in [ "utf-8", "latin-1", "iso-8859-1", "iso-latin-1" ] {
+        if name == prefix || name.starts_with(&format!("{}-", prefix)) {
+            return match prefix {
+                "utf-8" => String::from("utf-8"),
+                _ => String::from("iso-8859-1")
+            }
+        }
+    }
+    name
+}
+
+
+// Unittests for encoding detection ////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_default_utf8() {
+        assert_eq!(detect_encoding(b"x = 1\n"), Ok(( SourceEncoding::Utf8, 0 )));
+        assert_eq!(decode_source("x = 'å'\n".as_bytes()), Ok(String::from("x = 'å'\n")));
+    }
+
+    #[test]
+    fn detect_bom() {
+        assert_eq!(detect_encoding(b"\xef\xbb\xbfx = 1\n"), Ok(( SourceEncoding::Utf8, 3 )));
+        assert_eq!(decode_source(b"\xef\xbb\xbf# coding: utf-8\nx\n"), Ok(String::from("# coding: utf-8\nx\n")));
+    }
+
+    #[test]
+    fn detect_cookie_on_first_and_second_line() {
+        assert_eq!(detect_encoding(b"# -*- coding: latin-1 -*-\n"), Ok(( SourceEncoding::Latin1, 0 )));
+        assert_eq!(detect_encoding(b"#!/usr/bin/python\n# vim: set fileencoding=cp1252 :\n"), Ok(( SourceEncoding::Cp1252, 0 )));
+        assert_eq!(detect_encoding(b"\n#coding=ASCII\n"), Ok(( SourceEncoding::Ascii, 0 )));
+        assert_eq!(detect_encoding(b"# coding: UTF_8-unix\n"), Ok(( SourceEncoding::Utf8, 0 )));
+    }
+
+    #[test]
+    fn detect_cookie_ignored_after_code_or_on_third_line() {
+        assert_eq!(detect_encoding(b"x = 1\n# coding: latin-1\n"), Ok(( SourceEncoding::Utf8, 0 )));
+        assert_eq!(detect_encoding(b"#\n#\n# coding: latin-1\n"), Ok(( SourceEncoding::Utf8, 0 )));
+        assert_eq!(detect_encoding(b"x = 1 # coding: latin-1\n"), Ok(( SourceEncoding::Utf8, 0 )));
+    }
+
+    #[test]
+    fn detect_errors() {
+        assert_eq!(detect_encoding(b"# coding: ebcdic\n"), Err(String::from("unknown encoding: ebcdic")));
+        assert_eq!(detect_encoding(b"\xef\xbb\xbf# coding: latin-1\n"), Err(String::from("encoding problem: latin-1 with BOM")));
+    }
+
+    #[test]
+    fn decode_encodings() {
+        assert_eq!(decode_source(b"# coding: latin-1\ns = '\xe5'\n"), Ok(String::from("# coding: latin-1\ns = 'å'\n")));
+        assert_eq!(decode_source(b"# coding: cp1252\ns = '\x80'\n"), Ok(String::from("# coding: cp1252\ns = '€'\n")));
+        assert_eq!(decode_source(b"# coding: cp1252\ns = '\x81'\n"), Err(String::from("'charmap' codec can't decode byte 0x81 in position 22: character maps to <undefined>")));
+        assert_eq!(decode_source(b"# coding: ascii\ns = '\xe5'\n"), Err(String::from("'ascii' codec can't decode byte 0xe5 in position 21: ordinal not in range(128)")));
+        assert_eq!(decode_source(b"s = '\xff'\n"), Err(String::from("'utf-8' codec can't decode byte 0xff in position 5: invalid start byte")));
+    }
+}
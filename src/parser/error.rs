@@ -0,0 +1,98 @@
+use std::fmt;
+
+// Errors from tokenizer ///////////////////////////////////////////////////////////////////////////
+
+// Error found while tokenizing, with offsets of start and end of the offending source text. Offsets are
+// in units of the source buffer, which are characters for a buffer of characters and bytes for borrowed
+// UTF-8 text. Messages are worded as in CPython, and error_message() of the tokenizer adds the line where
+// an unterminated literal is detected, as line numbers are not known from offsets alone.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum TokenizerError {
+    UnterminatedString(u32, u32, bool),
+    UnterminatedFString(u32, u32, bool),
+    InvalidFString(u32, u32, &'static str),
+    UnindentMismatch(u32, u32),
+    InconsistentTabs(u32, u32),
+    TooDeepIndentation(u32, u32),
+    InvalidCharacter(u32, u32, char),
+    InvalidNumber(u32, u32, &'static str),
+    InvalidDigit(u32, u32, char, &'static str),
+    LeadingZeros(u32, u32),
+    UnmatchedBracket(u32, u32, char),
+    MismatchedBracket(u32, u32, char, char, Option<u32>),
+    TooManyParentheses(u32, u32),
+    UnexpectedEof(u32, u32),
+    UnclosedBracket(u32, u32, char),
+    UnexpectedCharacterAfterContinuation(u32, u32),
+    InvalidEncoding(u32, u32, Box<str>),
+    InvalidEscape(u32, u32, Box<str>),
+    NonAsciiBytes(u32, u32),
+    MixedBytesLiteral(u32, u32)
+}
+
+impl TokenizerError {
+    // Character offsets of start and end of source text in error. Unterminated strings ends where the
+    // end of the string was expected, and encoding errors are found before there is any source text.
+    pub fn offsets(&self) -> (u32, u32) {
+        match self {
+            TokenizerError::UnterminatedString(start, end, _) |
+            TokenizerError::UnterminatedFString(start, end, _) |
+            TokenizerError::InvalidFString(start, end, _) |
+            TokenizerError::UnindentMismatch(start, end) |
+            TokenizerError::InconsistentTabs(start, end) |
+            TokenizerError::TooDeepIndentation(start, end) |
+            TokenizerError::InvalidCharacter(start, end, _) |
+            TokenizerError::InvalidNumber(start, end, _) |
+            TokenizerError::InvalidDigit(start, end, _, _) |
+            TokenizerError::LeadingZeros(start, end) |
+            TokenizerError::UnmatchedBracket(start, end, _) |
+            TokenizerError::MismatchedBracket(start, end, _, _, _) |
+            TokenizerError::TooManyParentheses(start, end) |
+            TokenizerError::UnexpectedEof(start, end) |
+            TokenizerError::UnclosedBracket(start, end, _) |
+            TokenizerError::UnexpectedCharacterAfterContinuation(start, end) |
+            TokenizerError::InvalidEncoding(start, end, _) |
+            TokenizerError::InvalidEscape(start, end, _) |
+            TokenizerError::NonAsciiBytes(start, end) |
+            TokenizerError::MixedBytesLiteral(start, end) => ( *start, *end )
+        }
+    }
+}
+
+impl fmt::Display for TokenizerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenizerError::UnterminatedString(_, _, true) => write!(f, "unterminated triple-quoted string literal"),
+            TokenizerError::UnterminatedString(_, _, false) => write!(f, "unterminated string literal"),
+            TokenizerError::UnterminatedFString(_, _, true) => write!(f, "unterminated triple-quoted f-string literal"),
+            TokenizerError::UnterminatedFString(_, _, false) => write!(f, "unterminated f-string literal"),
+            TokenizerError::InvalidFString(_, _, message) => write!(f, "{}", message),
+            TokenizerError::UnindentMismatch(_, _) => write!(f, "unindent does not match any outer indentation level"),
+            TokenizerError::InconsistentTabs(_, _) => write!(f, "inconsistent use of tabs and spaces in indentation"),
+            TokenizerError::TooDeepIndentation(_, _) => write!(f, "too many levels of indentation"),
+            TokenizerError::InvalidCharacter(_, _, c) if !is_printable(*c) => write!(f, "invalid non-printable character U+{:04X}", *c as u32),
+            TokenizerError::InvalidCharacter(_, _, c) => write!(f, "invalid character '{}' (U+{:04X})", c, *c as u32),
+            TokenizerError::InvalidNumber(_, _, kind) => write!(f, "invalid {} literal", kind),
+            TokenizerError::InvalidDigit(_, _, c, kind) => write!(f, "invalid digit '{}' in {} literal", c, kind),
+            TokenizerError::LeadingZeros(_, _) => write!(f, "leading zeros in decimal integer literals are not permitted; use an 0o prefix for octal integers"),
+            TokenizerError::UnmatchedBracket(_, _, c) => write!(f, "unmatched '{}'", c),
+            TokenizerError::MismatchedBracket(_, _, close, open, None) => write!(f, "closing parenthesis '{}' does not match opening parenthesis '{}'", close, open),
+            TokenizerError::MismatchedBracket(_, _, close, open, Some(line)) => write!(f, "closing parenthesis '{}' does not match opening parenthesis '{}' on line {}", close, open, line),
+            TokenizerError::TooManyParentheses(_, _) => write!(f, "too many nested parentheses"),
+            TokenizerError::UnexpectedEof(_, _) => write!(f, "unexpected EOF in multi-line statement"),
+            TokenizerError::UnclosedBracket(_, _, c) => write!(f, "'{}' was never closed", c),
+            TokenizerError::UnexpectedCharacterAfterContinuation(_, _) => write!(f, "unexpected character after line continuation character"),
+            TokenizerError::InvalidEncoding(_, _, message) => write!(f, "{}", message),
+            TokenizerError::InvalidEscape(_, _, message) => write!(f, "{}", message),
+            TokenizerError::NonAsciiBytes(_, _) => write!(f, "bytes can only contain ASCII literal characters"),
+            TokenizerError::MixedBytesLiteral(_, _) => write!(f, "cannot mix bytes and nonbytes literals")
+        }
+    }
+}
+
+impl std::error::Error for TokenizerError {}
+
+// Characters CPython shows in error messages, other characters are shown as code point only.
+fn is_printable(c: char) -> bool {
+    !( c.is_control() || ( c.is_whitespace() && c != ' ' ) || matches!(c, '\u{ad}' | '\u{200b}' ..= '\u{200f}' | '\u{2060}' ..= '\u{2064}' | '\u{feff}') )
+}
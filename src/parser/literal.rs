@@ -0,0 +1,264 @@
+use std::fmt;
+use crate::parser::error::TokenizerError;
+
+// Decoding of string and bytes literals ///////////////////////////////////////////////////////////
+
+// Value of a string or bytes literal with escape sequences decoded.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum LiteralValue {
+    Str(String),
+    Bytes(Vec<u8>)
+}
+
+// Warnings given by CPython as SyntaxWarning while decoding literals, with offsets of start and end of
+// the escape sequence. The literal is still decoded, with invalid escape sequences kept as written.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum LiteralWarning {
+    InvalidEscape(u32, u32, char),
+    InvalidOctalEscape(u32, u32, u32)
+}
+
+impl LiteralWarning {
+    // Offsets of start and end of escape sequence warned about.
+    pub fn offsets(&self) -> (u32, u32) {
+        match self {
+            LiteralWarning::InvalidEscape(start, end, _) |
+            LiteralWarning::InvalidOctalEscape(start, end, _) => ( *start, *end )
+        }
+    }
+}
+
+impl fmt::Display for LiteralWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LiteralWarning::InvalidEscape(_, _, c) => write!(f, "invalid escape sequence '\\{}'", c),
+            LiteralWarning::InvalidOctalEscape(_, _, value) => write!(f, "invalid octal escape sequence '\\{:o}'", value)
+        }
+    }
+}
+
+// Decodes a single literal as written in source, with its prefix and quotes, found at offset start.
+// Width gives number of offsets taken by a character, as offsets count characters or bytes of source.
+// Line breaks inside the literal are read as '\n' like CPython does for source. Escapes of characters
+// which Rust strings cannot hold, the lone surrogates, are decoded as U+FFFD.
+pub fn decode_literal(text: &str, start: u32, width: fn(char) -> u32, warnings: &mut Vec<LiteralWarning>) -> Result<LiteralValue, TokenizerError> {
+    let prefix_length = text.find([ '\'', '"' ]).unwrap_or(text.len());
+    let prefix = &text[.. prefix_length];
+    let is_raw = prefix.contains([ 'r', 'R' ]);
+    let is_bytes = prefix.contains([ 'b', 'B' ]);
+    let quotes = &text[prefix_length ..];
+    let quote_length = if quotes.len() >= 6 && ( quotes.starts_with("'''") || quotes.starts_with("\"\"\"") ) { 3 } else { 1 };
+    let body = quotes.get(quote_length .. quotes.len().saturating_sub(quote_length)).unwrap_or("");
+
+    // Characters of body with their byte position in body and offset in source.
+    let mut offset = start + ( prefix_length + quote_length ) as u32;
+    let mut chars = Vec::with_capacity(body.len());
+    for ( position, c ) in body.char_indices() {
+        chars.push(( position, offset, c ));
+        offset += width(c)
+    }
+    let end_of = |index: usize| chars.get(index).map_or(offset, |( _, offset, _ )| *offset);
+
+    let mut decoded = String::with_capacity(body.len());
+    let mut index = 0;
+    while index < chars.len() {
+        let ( position, offset, c ) = chars[index];
+        let next = chars.get(index + 1).map_or('\0', |( _, _, c )| *c);
+        if is_bytes && !c.is_ascii() {
+            return Err(TokenizerError::NonAsciiBytes(offset, end_of(index + 1)))
+        }
+        if c == '\r' {
+            decoded.push('\n');
+            index += if next == '\n' { 2 } else { 1 };
+            continue
+        }
+        if c != '\\' || is_raw || index + 1 == chars.len() {
+            decoded.push(c);
+            index += 1;
+            continue
+        }
+
+        // Escape sequence, consuming backslash and character following it.
+        index += 2;
+        match next {
+            '\n' => (),
+            '\r' => {
+                if matches!(chars.get(index), Some(( _, _, '\n' ))) {
+                    index += 1
+                }
+            },
+            '\\' | '\'' | '"' => decoded.push(next),
+            'a' => decoded.push('\x07'),
+            'b' => decoded.push('\x08'),
+            'f' => decoded.push('\x0c'),
+            'n' => decoded.push('\n'),
+            'r' => decoded.push('\r'),
+            't' => decoded.push('\t'),
+            'v' => decoded.push('\x0b'),
+            '0' ..= '7' => {
+                let mut value = next.to_digit(8).unwrap_or(0);
+                let mut digits = 1;
+                while digits < 3 {
+                    match chars.get(index).and_then(|( _, _, c )| c.to_digit(8)) {
+                        Some(digit) => value = value * 8 + digit,
+                        _ => break
+                    }
+                    index += 1;
+                    digits += 1
+                }
+                if value > 0o377 {
+                    warnings.push(LiteralWarning::InvalidOctalEscape(offset, end_of(index), value))
+                }
+                if is_bytes {
+                    decoded.push(char::from(( value & 0xff ) as u8))
+                }
+                else {
+                    decoded.push(char::from_u32(value).unwrap_or('\u{fffd}'))
+                }
+            },
+            'x' | 'u' | 'U' if !is_bytes || next == 'x' => {
+                let digits = match next { 'x' => 2, 'u' => 4, _ => 8 };
+                let mut value: u32 = 0;
+                for found in 0 .. digits {
+                    match chars.get(index).and_then(|( _, _, c )| c.to_digit(16)) {
+                        Some(digit) => value = value * 16 + digit,
+                        _ if is_bytes => {
+                            return Err(TokenizerError::InvalidEscape(offset, end_of(index), format!("(value error) invalid \\x escape at position {}", position).into_boxed_str()))
+                        },
+                        _ => {
+                            let kind = match next { 'x' => "\\xXX", 'u' => "\\uXXXX", _ => "\\UXXXXXXXX" };
+                            return Err(unicode_error(offset, end_of(index), position, position + 1 + found, &format!("truncated {} escape", kind)))
+                        }
+                    }
+                    index += 1
+                }
+                if value > 0x10ffff {
+                    return Err(unicode_error(offset, end_of(index), position, position + 1 + digits, "illegal Unicode character"))
+                }
+                if is_bytes {
+                    decoded.push(char::from(value as u8))
+                }
+                else {
+                    decoded.push(char::from_u32(value).unwrap_or('\u{fffd}'))
+                }
+            },
+            'N' if !is_bytes => {
+                let name_start = index + 1;
+                let mut name_end = name_start;
+                while matches!(chars.get(name_end), Some(( _, _, c )) if *c != '}') {
+                    name_end += 1
+                }
+                if !matches!(chars.get(index), Some(( _, _, '{' ))) || name_end >= chars.len() || name_end == name_start {
+                    let last = if matches!(chars.get(index), Some(( _, _, '{' ))) { name_end.min(chars.len() - 1) } else { index - 1 };
+                    return Err(unicode_error(offset, end_of(last + 1), position, chars[last].0, "malformed \\N character escape"))
+                }
+                let name = &body[chars[name_start].0 .. chars[name_end].0];
+                match unicode_names2::character(name) {
+                    Some(named) => decoded.push(named),
+                    _ => return Err(unicode_error(offset, end_of(name_end + 1), position, chars[name_end].0, "unknown Unicode character name"))
+                }
+                index = name_end + 1
+            },
+            _ => {
+                warnings.push(LiteralWarning::InvalidEscape(offset, end_of(index), next));
+                decoded.push('\\');
+                index -= 1
+            }
+        }
+    }
+
+    if is_bytes {
+        return Ok(LiteralValue::Bytes(decoded.chars().map(|c| c as u8).collect()))
+    }
+    Ok(LiteralValue::Str(decoded))
+}
+
+// Error from decoding escape sequence in a string literal, worded as the unicodeescape codec of CPython
+// with byte positions within the literal of first and last byte of the escape sequence.
+fn unicode_error(start: u32, end: u32, first: usize, last: usize, reason: &str) -> TokenizerError {
+    let message = format!("(unicode error) 'unicodeescape' codec can't decode bytes in position {}-{}: {}", first, last, reason);
+    TokenizerError::InvalidEscape(start, end, message.into_boxed_str())
+}
+
+
+#[cfg(test)]
+#[allow(clippy::assertions_on_constants)]
+mod tests {
+    use super::*;
+
+    fn decode(text: &str) -> Result<(LiteralValue, Vec<LiteralWarning>), TokenizerError> {
+        let mut warnings = Vec::new();
+        let value = decode_literal(text, 0, |_| 1, &mut warnings)?;
+        Ok(( value, warnings ))
+    }
+
+    #[test]
+    fn decode_simple_escapes() {
+        assert_eq!(decode(r#"'a\n\t\\\'\"\a\b\f\r\v'"#), Ok(( LiteralValue::Str(String::from("a\n\t\\'\"\x07\x08\x0c\r\x0b")), vec![] )));
+    }
+
+    #[test]
+    fn decode_numeric_escapes() {
+        assert_eq!(decode(r"'\x41\u00e5\U0001F600\101\0'"), Ok(( LiteralValue::Str(String::from("Aå😀A\0")), vec![] )));
+        assert_eq!(decode(r"b'\x41\xff\101\0'"), Ok(( LiteralValue::Bytes(vec![ 0x41, 0xff, 0x41, 0 ]), vec![] )));
+    }
+
+    #[test]
+    fn decode_named_escapes() {
+        assert_eq!(decode(r"'\N{LATIN SMALL LETTER A}\N{snowman}\N{BACKSPACE}'"), Ok(( LiteralValue::Str(String::from("a☃\x08")), vec![] )));
+        match decode(r"'\N{NO SUCH NAME}'") {
+            Err(error) => assert_eq!(error.to_string(), "(unicode error) 'unicodeescape' codec can't decode bytes in position 0-15: unknown Unicode character name"),
+            _ => assert!(false)
+        }
+        match decode(r"'\N'") {
+            Err(error) => assert_eq!(error.to_string(), "(unicode error) 'unicodeescape' codec can't decode bytes in position 0-1: malformed \\N character escape"),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn decode_line_continuation_and_line_breaks() {
+        assert_eq!(decode("'''a\\\nb\r\nc\rd'''"), Ok(( LiteralValue::Str(String::from("ab\nc\nd")), vec![] )));
+        assert_eq!(decode("'a\\\r\nb'"), Ok(( LiteralValue::Str(String::from("ab")), vec![] )));
+    }
+
+    #[test]
+    fn decode_raw_literals() {
+        assert_eq!(decode(r"r'\n\d'"), Ok(( LiteralValue::Str(String::from(r"\n\d")), vec![] )));
+        assert_eq!(decode(r#"Rb"\x41""#), Ok(( LiteralValue::Bytes(b"\\x41".to_vec()), vec![] )));
+    }
+
+    #[test]
+    fn decode_invalid_escapes_as_warnings() {
+        assert_eq!(decode(r"'\d\777'"), Ok(( LiteralValue::Str(String::from("\\d\u{1ff}")), vec![
+            LiteralWarning::InvalidEscape(1, 3, 'd'),
+            LiteralWarning::InvalidOctalEscape(3, 7, 0o777)
+        ] )));
+        assert_eq!(decode(r"b'\N{DASH}\u0041'"), Ok(( LiteralValue::Bytes(b"\\N{DASH}\\u0041".to_vec()), vec![
+            LiteralWarning::InvalidEscape(2, 4, 'N'),
+            LiteralWarning::InvalidEscape(10, 12, 'u')
+        ] )));
+        assert_eq!(LiteralWarning::InvalidOctalEscape(3, 7, 0o777).to_string(), "invalid octal escape sequence '\\777'");
+    }
+
+    #[test]
+    fn decode_truncated_escapes() {
+        match decode(r"'ab\x4'") {
+            Err(error) => assert_eq!(error.to_string(), "(unicode error) 'unicodeescape' codec can't decode bytes in position 2-4: truncated \\xXX escape"),
+            _ => assert!(false)
+        }
+        match decode(r"b'\x4'") {
+            Err(error) => assert_eq!(error.to_string(), "(value error) invalid \\x escape at position 0"),
+            _ => assert!(false)
+        }
+        match decode(r"'\U00110000'") {
+            Err(error) => assert_eq!(error.to_string(), "(unicode error) 'unicodeescape' codec can't decode bytes in position 0-9: illegal Unicode character"),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn decode_non_ascii_bytes() {
+        assert_eq!(decode("b'å'"), Err(TokenizerError::NonAsciiBytes(2, 3)));
+    }
+}
@@ -0,0 +1,223 @@
+
+// Data structures for positions ///////////////////////////////////////////////////////////////////
+
+// Position of a character in source, with line counted from one and columns counted from zero as
+// characters, UTF-8 bytes and UTF-16 code units.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Position {
+    pub line: u32,
+    pub column: u32,
+    pub utf8_column: u32,
+    pub utf16_column: u32
+}
+
+// Start and end position of a token, where end is just after its last character.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position
+}
+
+// Character offsets where each line starts, used for converting offsets into positions.
+pub struct LineIndex {
+    line_starts: Vec<u32>
+}
+
+// Implementation of line index ////////////////////////////////////////////////////////////////////
+
+impl LineIndex {
+    // Lines are terminated by '\n', '\r\n' or a single '\r' as in universal newline mode of CPython.
+    pub fn new(source: &[char]) -> Self {
+        let mut line_starts = vec![ 0 ];
+        let mut index = 0;
+        while index < source.len() {
+            match source[index] {
+                '\r' if source.get(index + 1) == Some(&'\n') => {
+                    index += 1;
+                    line_starts.push(index as u32 + 1)
+                },
+                '\r' | '\n' => line_starts.push(index as u32 + 1),
+                _ => ()
+            }
+            index += 1
+        }
+        LineIndex { line_starts }
+    }
+
+    // Same as new(), with lines starting at byte offsets of UTF-8 text instead of character offsets.
+    pub fn new_utf8(source: &str) -> Self {
+        let bytes = source.as_bytes();
+        let mut line_starts = vec![ 0 ];
+        let mut index = 0;
+        while index < bytes.len() {
+            match bytes[index] {
+                b'\r' if bytes.get(index + 1) == Some(&b'\n') => {
+                    index += 1;
+                    line_starts.push(index as u32 + 1)
+                },
+                b'\r' | b'\n' => line_starts.push(index as u32 + 1),
+                _ => ()
+            }
+            index += 1
+        }
+        LineIndex { line_starts }
+    }
+
+    pub fn line_count(&self) -> u32 {
+        self.line_starts.len() as u32
+    }
+
+    // Character offset of first character of line, counted from one.
+    pub fn line_start(&self, line: u32) -> Option<u32> {
+        match line {
+            0 => None,
+            _ => self.line_starts.get(line as usize - 1).copied()
+        }
+    }
+
+    // Line, counted from one, of the line holding offset.
+    pub fn line(&self, offset: u32) -> u32 {
+        match self.line_starts.binary_search(&offset) {
+            Ok(line) => line as u32 + 1,
+            Err(line) => line as u32
+        }
+    }
+
+    // Converts character offset into position, offsets past end of source gives position after last character.
+    pub fn position(&self, source: &[char], offset: u32) -> Position {
+        let offset = offset.min(source.len() as u32);
+        let line = self.line(offset);
+        let line_start = self.line_starts[line as usize - 1];
+        let mut position = Position { line, column: 0, utf8_column: 0, utf16_column: 0 };
+        for c in &source[line_start as usize .. offset as usize] {
+            position.column += 1;
+            position.utf8_column += c.len_utf8() as u32;
+            position.utf16_column += c.len_utf16() as u32
+        }
+        position
+    }
+
+    // Converts start and end offset into span. End position is taken just after last character of
+    // token, so a token ending with a line break still ends on its own line as in CPython.
+    pub fn span(&self, source: &[char], start: u32, end: u32) -> Span {
+        let start_position = self.position(source, start);
+        let end = end.min(source.len() as u32);
+        if end <= start {
+            return Span { start: start_position, end: start_position }
+        }
+        let mut end_position = self.position(source, end - 1);
+        let c = source[end as usize - 1];
+        end_position.column += 1;
+        end_position.utf8_column += c.len_utf8() as u32;
+        end_position.utf16_column += c.len_utf16() as u32;
+        Span { start: start_position, end: end_position }
+    }
+
+    // Same as position(), for byte offset in UTF-8 text with line index made by new_utf8(). Offsets inside
+    // a character are taken as the start of that character.
+    pub fn position_utf8(&self, source: &str, offset: u32) -> Position {
+        let mut offset = ( offset as usize ).min(source.len());
+        while !source.is_char_boundary(offset) {
+            offset -= 1
+        }
+        let line = self.line(offset as u32);
+        let line_start = self.line_starts[line as usize - 1] as usize;
+        let mut position = Position { line, column: 0, utf8_column: 0, utf16_column: 0 };
+        for c in source[line_start .. offset].chars() {
+            position.column += 1;
+            position.utf8_column += c.len_utf8() as u32;
+            position.utf16_column += c.len_utf16() as u32
+        }
+        position
+    }
+
+    // Same as span(), for byte offsets in UTF-8 text with line index made by new_utf8().
+    pub fn span_utf8(&self, source: &str, start: u32, end: u32) -> Span {
+        let start_position = self.position_utf8(source, start);
+        let end = ( end as usize ).min(source.len());
+        let c = match source.get(.. end).and_then(|text| text.chars().next_back()) {
+            Some(c) if end > start as usize => c,
+            _ => return Span { start: start_position, end: start_position }
+        };
+        let mut end_position = self.position_utf8(source, ( end - c.len_utf8() ) as u32);
+        end_position.column += 1;
+        end_position.utf8_column += c.len_utf8() as u32;
+        end_position.utf16_column += c.len_utf16() as u32;
+        Span { start: start_position, end: end_position }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_index_line_starts() {
+        let source : Vec<char> = "a\nb\r\nc\rd".chars().collect();
+        let index = LineIndex::new(&source);
+        assert_eq!(index.line_count(), 4);
+        assert_eq!(index.line_start(1), Some(0));
+        assert_eq!(index.line_start(2), Some(2));
+        assert_eq!(index.line_start(3), Some(5));
+        assert_eq!(index.line_start(4), Some(7));
+        assert_eq!(index.line_start(5), None);
+        assert_eq!(index.line_start(0), None)
+    }
+
+    #[test]
+    fn line_index_utf8_line_starts() {
+        let index = LineIndex::new_utf8("å\nb\r\nc");
+        assert_eq!(index.line_count(), 3);
+        assert_eq!(index.line_start(2), Some(3));
+        assert_eq!(index.line_start(3), Some(6));
+        assert_eq!(index.line(2), 1);
+        assert_eq!(index.line(3), 2);
+        assert_eq!(index.line(100), 3)
+    }
+
+    #[test]
+    fn line_index_position_columns() {
+        let source : Vec<char> = "x\né😀 = 1".chars().collect();
+        let index = LineIndex::new(&source);
+        assert_eq!(index.position(&source, 0), Position { line: 1, column: 0, utf8_column: 0, utf16_column: 0 });
+        assert_eq!(index.position(&source, 2), Position { line: 2, column: 0, utf8_column: 0, utf16_column: 0 });
+        assert_eq!(index.position(&source, 5), Position { line: 2, column: 3, utf8_column: 7, utf16_column: 4 });
+        assert_eq!(index.position(&source, 100), Position { line: 2, column: 6, utf8_column: 10, utf16_column: 7 })
+    }
+
+    #[test]
+    fn line_index_span_ending_with_newline() {
+        let source : Vec<char> = "a = 1\r\nb".chars().collect();
+        let index = LineIndex::new(&source);
+        let span = index.span(&source, 5, 7);
+        assert_eq!(span.start, Position { line: 1, column: 5, utf8_column: 5, utf16_column: 5 });
+        assert_eq!(span.end, Position { line: 1, column: 7, utf8_column: 7, utf16_column: 7 })
+    }
+
+    #[test]
+    fn line_index_utf8_same_as_characters() {
+        let text = "x\né😀 = 'å'\r\n";
+        let source : Vec<char> = text.chars().collect();
+        let index = LineIndex::new(&source);
+        let utf8_index = LineIndex::new_utf8(text);
+        for ( offset, ( byte_offset, c ) ) in text.char_indices().enumerate() {
+            let end = byte_offset + c.len_utf8();
+            assert_eq!(utf8_index.position_utf8(text, byte_offset as u32), index.position(&source, offset as u32));
+            assert_eq!(utf8_index.span_utf8(text, byte_offset as u32, end as u32), index.span(&source, offset as u32, offset as u32 + 1))
+        }
+        assert_eq!(utf8_index.position_utf8(text, 3), utf8_index.position_utf8(text, 2));
+        assert_eq!(utf8_index.position_utf8(text, 100), index.position(&source, 100))
+    }
+
+    #[test]
+    fn line_index_empty_span() {
+        let source : Vec<char> = "a\n".chars().collect();
+        let index = LineIndex::new(&source);
+        let span = index.span(&source, 2, 2);
+        assert_eq!(span.start, Position { line: 2, column: 0, utf8_column: 0, utf16_column: 0 });
+        assert_eq!(span.end, span.start)
+    }
+}
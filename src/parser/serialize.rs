@@ -0,0 +1,285 @@
+use crate::parser::token::{TokenInfo, TokenKind, TokenSymbol};
+use crate::parser::tokenizer::{match_keyword, match_operator_or_delimiter, match_soft_keyword, PythonCoreTokenizer};
+
+// Serialization of token streams //////////////////////////////////////////////////////////////////
+
+// Start of binary token streams, followed by version of encoding.
+const MAGIC: &[u8] = b"PYTK";
+const VERSION: u8 = 1;
+
+// Kinds of tokens by their number in binary token streams, in order of declaration in TokenKind.
+const KINDS: [TokenKind; 101] = [
+    TokenKind::PyEof, TokenKind::PyNewline, TokenKind::PyNl, TokenKind::PyIndent,
+    TokenKind::PyDedent, TokenKind::PyFalse, TokenKind::PyNone, TokenKind::PyTrue,
+    TokenKind::PyAnd, TokenKind::PyAs, TokenKind::PyAssert, TokenKind::PyAsync, TokenKind::PyAwait,
+    TokenKind::PyBreak, TokenKind::PyClass, TokenKind::PyContinue, TokenKind::PyDef,
+    TokenKind::PyDel, TokenKind::PyElif, TokenKind::PyElse, TokenKind::PyExcept,
+    TokenKind::PyFinally, TokenKind::PyFor, TokenKind::PyFrom, TokenKind::PyGlobal,
+    TokenKind::PyIf, TokenKind::PyImport, TokenKind::PyIn, TokenKind::PyIs, TokenKind::PyLambda,
+    TokenKind::PyNonlocal, TokenKind::PyNot, TokenKind::PyOr, TokenKind::PyPass,
+    TokenKind::PyRaise, TokenKind::PyReturn, TokenKind::PyTry, TokenKind::PyWhile,
+    TokenKind::PyWith, TokenKind::PyYield, TokenKind::PyMatch, TokenKind::PyCase,
+    TokenKind::PyType, TokenKind::PyUnderscore, TokenKind::PyPlus, TokenKind::PyMinus,
+    TokenKind::PyMul, TokenKind::PyPower, TokenKind::PyDiv, TokenKind::PyFloorDiv,
+    TokenKind::PyModulo, TokenKind::PyMatrices, TokenKind::PyShiftLeft, TokenKind::PyShiftRight,
+    TokenKind::PyBitAnd, TokenKind::PyBitOr, TokenKind::PyBitXor, TokenKind::PyBitInvert,
+    TokenKind::PyColonAssign, TokenKind::PyLess, TokenKind::PyGreater, TokenKind::PyLessEqual,
+    TokenKind::PyGreaterEqual, TokenKind::PyEqual, TokenKind::PyNotEqual, TokenKind::PyExclamation,
+    TokenKind::PyLeftParen, TokenKind::PyRightParen, TokenKind::PyLeftBracket,
+    TokenKind::PyRightBracket, TokenKind::PyLeftCurly, TokenKind::PyRightCurly, TokenKind::PyComma,
+    TokenKind::PyColon, TokenKind::PyDot, TokenKind::PyEllipsis, TokenKind::PySemiColon,
+    TokenKind::PyAssign, TokenKind::PyArrow, TokenKind::PyPlusAssign, TokenKind::PyMinusAssign,
+    TokenKind::PyMulAssign, TokenKind::PyDivAssign, TokenKind::PyFloorDivAssign,
+    TokenKind::PyModuloAssign, TokenKind::PyMatricesAssign, TokenKind::PyBitAndAssign,
+    TokenKind::PyBitOrAssign, TokenKind::PyBitXorAssign, TokenKind::PyShiftLeftAssign,
+    TokenKind::PyShiftRightAssign, TokenKind::PyPowerAssign, TokenKind::PyName,
+    TokenKind::PyNumber, TokenKind::PyString, TokenKind::PyFStringStart,
+    TokenKind::PyFStringMiddle, TokenKind::PyFStringEnd, TokenKind::PyTypeComment,
+    TokenKind::PyComment, TokenKind::PyError
+];
+
+// Writes tokens returned by tokenizer as a JSON array of TokenInfo objects, in the shape of tokens
+// from the tokenize module of CPython with type, string, start, end and line. Implicitly concatenated
+// strings are written as one STRING for each literal.
+pub fn to_json(tokenizer: &PythonCoreTokenizer, tokens: &[Box<TokenSymbol>]) -> String {
+    let infos: Vec<TokenInfo> = tokens.iter().flat_map(|symbol| tokenizer.token_infos(symbol)).collect();
+    // Serializing strings and numbers into a string can not fail.
+    serde_json::to_string(&infos).unwrap_or_default()
+}
+
+// Reads tokens written by to_json().
+pub fn from_json(text: &str) -> Result<Vec<TokenInfo>, String> {
+    serde_json::from_str(text).map_err(|error| error.to_string())
+}
+
+// Encodes tokens compactly for caching. Each token is written as its kind in one byte, its start as
+// difference from start of previous token and its length, followed by its text as length and UTF-8
+// bytes, or number of parts and each part for PyString. Numbers are LEB128, with zigzag for signs.
+pub fn to_binary(tokens: &[Box<TokenSymbol>]) -> Vec<u8> {
+    let mut bytes = Vec::from(MAGIC);
+    bytes.push(VERSION);
+    write_number(&mut bytes, tokens.len() as u64);
+    let mut previous_start: i64 = 0;
+    for symbol in tokens {
+        let ( start, end ) = symbol.offsets();
+        bytes.push(symbol.kind() as u8);
+        write_signed(&mut bytes, start as i64 - previous_start);
+        write_signed(&mut bytes, end as i64 - start as i64);
+        previous_start = start as i64;
+        match &**symbol {
+            TokenSymbol::PyString(_, _, parts) => {
+                write_number(&mut bytes, parts.len() as u64);
+                for part in parts.iter() {
+                    write_text(&mut bytes, part)
+                }
+            },
+            TokenSymbol::PyName(_, _, text) |
+            TokenSymbol::PyNumber(_, _, text) |
+            TokenSymbol::PyFStringStart(_, _, text) |
+            TokenSymbol::PyFStringMiddle(_, _, text) |
+            TokenSymbol::PyFStringEnd(_, _, text) |
+            TokenSymbol::PyTypeComment(_, _, text) |
+            TokenSymbol::PyComment(_, _, text) |
+            TokenSymbol::PyError(_, _, text) => write_text(&mut bytes, text),
+            _ => ()
+        }
+    }
+    bytes
+}
+
+// Decodes tokens encoded by to_binary().
+pub fn from_binary(bytes: &[u8]) -> Result<Box<Vec<Box<TokenSymbol>>>, String> {
+    if !bytes.starts_with(MAGIC) || bytes.get(MAGIC.len()) != Some(&VERSION) {
+        return Err(format!("not a binary token stream of version {}", VERSION))
+    }
+    let mut reader = Reader { bytes, index: MAGIC.len() + 1 };
+    let count = reader.number()?;
+    let mut tokens = Box::new(Vec::with_capacity(( count as usize ).min(bytes.len())));
+    let mut previous_start: i64 = 0;
+    for _ in 0 .. count {
+        let kind = match KINDS.get(reader.byte()? as usize) {
+            Some(kind) => *kind,
+            _ => return Err(String::from("unknown kind of token in binary token stream"))
+        };
+        let start = previous_start + reader.signed()?;
+        let end = start + reader.signed()?;
+        if start < 0 || end < start || end > u32::MAX as i64 {
+            return Err(String::from("offsets of token out of range in binary token stream"))
+        }
+        previous_start = start;
+        let ( start, end ) = ( start as u32, end as u32 );
+        let symbol = match kind {
+            TokenKind::PyEof => TokenSymbol::PyEof(start, end),
+            TokenKind::PyNewline => TokenSymbol::PyNewline(start, end),
+            TokenKind::PyNl => TokenSymbol::PyNl(start, end),
+            TokenKind::PyIndent => TokenSymbol::PyIndent(start, end),
+            TokenKind::PyDedent => TokenSymbol::PyDedent(start, end),
+            TokenKind::PyName => TokenSymbol::PyName(start, end, reader.text()?),
+            TokenKind::PyNumber => TokenSymbol::PyNumber(start, end, reader.text()?),
+            TokenKind::PyFStringStart => TokenSymbol::PyFStringStart(start, end, reader.text()?),
+            TokenKind::PyFStringMiddle => TokenSymbol::PyFStringMiddle(start, end, reader.text()?),
+            TokenKind::PyFStringEnd => TokenSymbol::PyFStringEnd(start, end, reader.text()?),
+            TokenKind::PyTypeComment => TokenSymbol::PyTypeComment(start, end, reader.text()?),
+            TokenKind::PyComment => TokenSymbol::PyComment(start, end, reader.text()?),
+            TokenKind::PyError => TokenSymbol::PyError(start, end, reader.text()?),
+            TokenKind::PyString => {
+                let count = reader.number()?;
+                let mut parts = Vec::new();
+                for _ in 0 .. count {
+                    parts.push(reader.text()?)
+                }
+                TokenSymbol::PyString(start, end, Box::new(parts))
+            },
+            _ => match symbol_from_spelling(kind, start, end) {
+                Some(symbol) => symbol,
+                _ => return Err(String::from("unknown kind of token in binary token stream"))
+            }
+        };
+        tokens.push(Box::new(symbol))
+    }
+    if reader.index != bytes.len() {
+        return Err(String::from("bytes left after end of binary token stream"))
+    }
+    Ok(tokens)
+}
+
+// Rebuilds token of keyword, soft keyword, operator or delimiter from its spelling.
+fn symbol_from_spelling(kind: TokenKind, start: u32, end: u32) -> Option<TokenSymbol> {
+    let spelling = kind.spelling()?;
+    match_keyword(spelling, start, end).or_else(|| match_soft_keyword(spelling, start, end)).or_else(|| {
+        let mut chars = spelling.chars().chain(std::iter::repeat('\0'));
+        let ( c1, c2, c3 ) = ( chars.next()?, chars.next()?, chars.next()? );
+        match_operator_or_delimiter(c1, c2, c3, start).map(|( symbol, _ )| symbol)
+    })
+}
+
+fn write_number(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push(( value as u8 & 0x7f ) | 0x80);
+        value >>= 7
+    }
+    bytes.push(value as u8)
+}
+
+fn write_signed(bytes: &mut Vec<u8>, value: i64) {
+    write_number(bytes, ( ( value << 1 ) ^ ( value >> 63 ) ) as u64)
+}
+
+fn write_text(bytes: &mut Vec<u8>, text: &str) {
+    write_number(bytes, text.len() as u64);
+    bytes.extend_from_slice(text.as_bytes())
+}
+
+// Reads numbers and texts of a binary token stream from its start.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    index: usize
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> Result<u8, String> {
+        match self.bytes.get(self.index) {
+            Some(byte) => {
+                self.index += 1;
+                Ok(*byte)
+            },
+            _ => Err(String::from("unexpected end of binary token stream"))
+        }
+    }
+
+    fn number(&mut self) -> Result<u64, String> {
+        let mut value: u64 = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.byte()?;
+            if shift >= 64 {
+                return Err(String::from("number too large in binary token stream"))
+            }
+            value |= ( ( byte & 0x7f ) as u64 ) << shift;
+            if byte < 0x80 {
+                return Ok(value)
+            }
+            shift += 7
+        }
+    }
+
+    fn signed(&mut self) -> Result<i64, String> {
+        let value = self.number()?;
+        Ok(( value >> 1 ) as i64 ^ -( ( value & 1 ) as i64 ))
+    }
+
+    fn text(&mut self) -> Result<Box<str>, String> {
+        let length = self.number()? as usize;
+        let end = match self.index.checked_add(length) {
+            Some(end) if end <= self.bytes.len() => end,
+            _ => return Err(String::from("unexpected end of binary token stream"))
+        };
+        let text = std::str::from_utf8(&self.bytes[self.index .. end]).map_err(|_| String::from("invalid UTF-8 in binary token stream"))?;
+        self.index = end;
+        Ok(Box::from(text))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::tokenizer::Tokenizer;
+
+    #[test]
+    fn kinds_in_order_of_declaration() {
+        for ( index, kind ) in KINDS.iter().enumerate() {
+            assert_eq!(*kind as usize, index)
+        }
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let source = "async def f(x: int) -> None:\n    y = [ 0x1f, 'a' \"b\", f'{x!r:>{10}}' ]  # done\n    return ... if _ else x ** -1 @ y\n";
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from(source), 4).with_comments(true);
+        let tokens = lexer.tokenize().unwrap();
+        let bytes = to_binary(&tokens);
+        assert!(bytes.len() < source.len() * 2);
+        assert_eq!(from_binary(&bytes), Ok(tokens))
+    }
+
+    #[test]
+    fn binary_rejects_damaged_streams() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("x = 'abc'\n"), 4);
+        let bytes = to_binary(&lexer.tokenize().unwrap());
+        assert_eq!(from_binary(&bytes[.. bytes.len() - 2]), Err(String::from("unexpected end of binary token stream")));
+        assert_eq!(from_binary(b"PYTK\x02"), Err(String::from("not a binary token stream of version 1")));
+        assert_eq!(from_binary(b"PYTK\x01\x01\xff\x00\x00"), Err(String::from("unknown kind of token in binary token stream")))
+    }
+
+    #[test]
+    fn json_as_cpython_tokenize() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("if x:\n    y **= 1\n"), 4);
+        let tokens = lexer.tokenize().unwrap();
+        let json = to_json(&lexer, &tokens);
+        assert!(json.starts_with(r#"[{"type":"NAME","string":"if","start":[1,0],"end":[1,2],"line":"if x:\n"},"#));
+        assert!(json.contains(r#"{"type":"NEWLINE","string":"\n","start":[1,5],"end":[1,6],"line":"if x:\n"}"#));
+        assert!(json.contains(r#"{"type":"INDENT","string":"    ","start":[2,0],"end":[2,4],"line":"    y **= 1\n"}"#));
+        assert!(json.contains(r#"{"type":"OP","string":"**=","start":[2,6],"end":[2,9],"line":"    y **= 1\n"}"#));
+        assert!(json.ends_with(r#"{"type":"ENDMARKER","string":"","start":[3,0],"end":[3,0],"line":""}]"#));
+        let infos = from_json(&json).unwrap();
+        assert_eq!(infos.len(), tokens.len());
+        assert_eq!(infos[1], lexer.token_info(&tokens[1]))
+    }
+
+    #[test]
+    fn json_of_concatenated_strings_as_cpython_tokenize() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("s = \"a\" 'b' \\\n    r\"c\"\n"), 4);
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens.len(), 5);
+        assert_eq!(to_json(&lexer, &tokens), concat!(
+            r#"[{"type":"NAME","string":"s","start":[1,0],"end":[1,1],"line":"s = \"a\" 'b' \\\n"},"#,
+            r#"{"type":"OP","string":"=","start":[1,2],"end":[1,3],"line":"s = \"a\" 'b' \\\n"},"#,
+            r#"{"type":"STRING","string":"\"a\"","start":[1,4],"end":[1,7],"line":"s = \"a\" 'b' \\\n"},"#,
+            r#"{"type":"STRING","string":"'b'","start":[1,8],"end":[1,11],"line":"s = \"a\" 'b' \\\n"},"#,
+            r#"{"type":"STRING","string":"r\"c\"","start":[2,4],"end":[2,8],"line":"    r\"c\"\n"},"#,
+            r#"{"type":"NEWLINE","string":"\n","start":[2,8],"end":[2,9],"line":"    r\"c\"\n"},"#,
+            r#"{"type":"ENDMARKER","string":"","start":[3,0],"end":[3,0],"line":""}]"#
+        ))
+    }
+}
@@ -0,0 +1,115 @@
+use std::borrow::Cow;
+use unicode_normalization::UnicodeNormalization;
+use crate::parser::location::{LineIndex, Span};
+
+// Source text scanned by tokenizer ////////////////////////////////////////////////////////////////
+
+// Source text a tokenizer scans. Offsets into a buffer of characters counts characters, while
+// offsets into borrowed UTF-8 text counts bytes so that tokens can hold slices of the text.
+pub trait SourceBuffer {
+    type Text: Clone;
+
+    // Character at offset, or '\0' outside of text. Offsets inside a multi-byte character of UTF-8
+    // text gives U+FFFD, which scanning treats as any other character that is not ASCII.
+    fn char_at(&self, offset: u32) -> char;
+
+    // Number of offsets taken by character.
+    fn width(c: char) -> u32;
+
+    fn length(&self) -> u32;
+
+    // Text between offsets, as used for names and keywords.
+    fn str_slice(&self, start: u32, end: u32) -> Cow<'_, str>;
+
+    // Text between offsets as held by tokens.
+    fn text(&self, start: u32, end: u32) -> Self::Text;
+
+    // Text of a name as held by PyName.
+    fn name_text(&self, start: u32, end: u32) -> Self::Text;
+
+    fn line_index(&self) -> LineIndex;
+
+    // Line and columns of start and end offset, with line index made by line_index().
+    fn span(&self, line_index: &LineIndex, start: u32, end: u32) -> Span;
+}
+
+// Characters copied from source, with names NFKC normalized as CPython does for identifiers.
+impl SourceBuffer for Vec<char> {
+    type Text = Box<str>;
+
+    fn char_at(&self, offset: u32) -> char {
+        match self.get(offset as usize) {
+            Some(c) => *c,
+            _ => '\0'
+        }
+    }
+
+    fn width(_: char) -> u32 {
+        1
+    }
+
+    fn length(&self) -> u32 {
+        self.len() as u32
+    }
+
+    fn str_slice(&self, start: u32, end: u32) -> Cow<'_, str> {
+        Cow::Owned(self[start as usize .. end as usize].iter().collect())
+    }
+
+    fn text(&self, start: u32, end: u32) -> Box<str> {
+        self[start as usize .. end as usize].iter().collect::<String>().into_boxed_str()
+    }
+
+    fn name_text(&self, start: u32, end: u32) -> Box<str> {
+        self[start as usize .. end as usize].iter().copied().nfkc().collect::<String>().into_boxed_str()
+    }
+
+    fn line_index(&self) -> LineIndex {
+        LineIndex::new(self)
+    }
+
+    fn span(&self, line_index: &LineIndex, start: u32, end: u32) -> Span {
+        line_index.span(self, start, end)
+    }
+}
+
+// Borrowed text, where tokens hold slices of the source and names are kept as written.
+impl<'a> SourceBuffer for &'a str {
+    type Text = &'a str;
+
+    fn char_at(&self, offset: u32) -> char {
+        match self.as_bytes().get(offset as usize) {
+            Some(b) if b.is_ascii() => *b as char,
+            Some(_) => self.get(offset as usize ..).and_then(|rest| rest.chars().next()).unwrap_or('\u{fffd}'),
+            _ => '\0'
+        }
+    }
+
+    fn width(c: char) -> u32 {
+        c.len_utf8() as u32
+    }
+
+    fn length(&self) -> u32 {
+        self.len() as u32
+    }
+
+    fn str_slice(&self, start: u32, end: u32) -> Cow<'_, str> {
+        Cow::Borrowed(&self[start as usize .. end as usize])
+    }
+
+    fn text(&self, start: u32, end: u32) -> &'a str {
+        &self[start as usize .. end as usize]
+    }
+
+    fn name_text(&self, start: u32, end: u32) -> &'a str {
+        &self[start as usize .. end as usize]
+    }
+
+    fn line_index(&self) -> LineIndex {
+        LineIndex::new_utf8(self)
+    }
+
+    fn span(&self, line_index: &LineIndex, start: u32, end: u32) -> Span {
+        line_index.span_utf8(self, start, end)
+    }
+}
@@ -1,10 +1,16 @@
+use std::fmt;
+use crate::parser::location::Span;
 
-#[derive(Clone)]
-pub enum TokenSymbol {
-    PyEof,
+// Tokens hold their text as T, which is Box<str> for tokens of PythonCoreTokenizer over a character
+// buffer and a slice of the source for tokens of a tokenizer borrowing its source.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub enum TokenSymbol<T = Box<str>> {
+    PyEof(u32, u32),
     PyNewline(u32, u32),
-    PyIndent,
-    PyDedent,
+    PyNl(u32, u32),
+    PyIndent(u32, u32),
+    PyDedent(u32, u32),
     PyFalse(u32, u32),
     PyNone(u32, u32),
     PyTrue(u32, u32),
@@ -41,6 +47,11 @@ pub enum TokenSymbol {
     PyWith(u32, u32),
     PyYield(u32, u32),
 
+    PyMatch(u32, u32),
+    PyCase(u32, u32),
+    PyType(u32, u32),
+    PyUnderscore(u32, u32),
+
     PyPlus(u32, u32),
     PyMinus(u32, u32),
     PyMul(u32, u32),
@@ -62,6 +73,7 @@ pub enum TokenSymbol {
     PyGreaterEqual(u32, u32),
     PyEqual(u32, u32),
     PyNotEqual(u32, u32),
+    PyExclamation(u32, u32),
     PyLeftParen(u32, u32),
     PyRightParen(u32, u32),
     PyLeftBracket(u32, u32),
@@ -89,8 +101,856 @@ pub enum TokenSymbol {
     PyShiftRightAssign(u32, u32),
     PyPowerAssign(u32, u32),
 
-    PyName(u32, u32, Box<str>),
-    PyNumber(u32, u32,Box<str>),
-    PyString(u32, u32, Box<Vec<Box<str>>>),
-    PyTypeComment(u32, u32, Box<str>)
-}
\ No newline at end of file
+    PyName(u32, u32, T),
+    PyNumber(u32, u32,T),
+    PyString(u32, u32, Box<Vec<T>>),
+    PyFStringStart(u32, u32, T),
+    PyFStringMiddle(u32, u32, T),
+    PyFStringEnd(u32, u32, T),
+    PyTypeComment(u32, u32, T),
+    PyComment(u32, u32, T),
+    PyError(u32, u32, T)
+}
+
+// Kind of token without its offsets and text, for comparing kinds of tokens cheaply.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum TokenKind {
+    PyEof,
+    PyNewline,
+    PyNl,
+    PyIndent,
+    PyDedent,
+    PyFalse,
+    PyNone,
+    PyTrue,
+    PyAnd,
+    PyAs,
+    PyAssert,
+    PyAsync,
+    PyAwait,
+    PyBreak,
+    PyClass,
+    PyContinue,
+    PyDef,
+    PyDel,
+    PyElif,
+    PyElse,
+    PyExcept,
+    PyFinally,
+    PyFor,
+    PyFrom,
+    PyGlobal,
+    PyIf,
+    PyImport,
+    PyIn,
+    PyIs,
+    PyLambda,
+    PyNonlocal,
+    PyNot,
+    PyOr,
+    PyPass,
+    PyRaise,
+    PyReturn,
+    PyTry,
+    PyWhile,
+    PyWith,
+    PyYield,
+
+    PyMatch,
+    PyCase,
+    PyType,
+    PyUnderscore,
+
+    PyPlus,
+    PyMinus,
+    PyMul,
+    PyPower,
+    PyDiv,
+    PyFloorDiv,
+    PyModulo,
+    PyMatrices,
+    PyShiftLeft,
+    PyShiftRight,
+    PyBitAnd,
+    PyBitOr,
+    PyBitXor,
+    PyBitInvert,
+    PyColonAssign,
+    PyLess,
+    PyGreater,
+    PyLessEqual,
+    PyGreaterEqual,
+    PyEqual,
+    PyNotEqual,
+    PyExclamation,
+    PyLeftParen,
+    PyRightParen,
+    PyLeftBracket,
+    PyRightBracket,
+    PyLeftCurly,
+    PyRightCurly,
+    PyComma,
+    PyColon,
+    PyDot,
+    PyEllipsis,
+    PySemiColon,
+    PyAssign,
+    PyArrow,
+    PyPlusAssign,
+    PyMinusAssign,
+    PyMulAssign,
+    PyDivAssign,
+    PyFloorDivAssign,
+    PyModuloAssign,
+    PyMatricesAssign,
+    PyBitAndAssign,
+    PyBitOrAssign,
+    PyBitXorAssign,
+    PyShiftLeftAssign,
+    PyShiftRightAssign,
+    PyPowerAssign,
+
+    PyName,
+    PyNumber,
+    PyString,
+    PyFStringStart,
+    PyFStringMiddle,
+    PyFStringEnd,
+    PyTypeComment,
+    PyComment,
+    PyError
+}
+
+// Classes of tokens for syntax highlighting.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum TokenCategory {
+    Keyword,
+    SoftKeyword,
+    Name,
+    Number,
+    String,
+    Comment,
+    Operator,
+    Delimiter,
+    Layout,
+    Error
+}
+
+impl TokenKind {
+    // Source spelling of keywords, soft keywords, operators and delimiters.
+    pub fn spelling(self) -> Option<&'static str> {
+        match self {
+            TokenKind::PyFalse => Some("False"),
+            TokenKind::PyNone => Some("None"),
+            TokenKind::PyTrue => Some("True"),
+            TokenKind::PyAnd => Some("and"),
+            TokenKind::PyAs => Some("as"),
+            TokenKind::PyAssert => Some("assert"),
+            TokenKind::PyAsync => Some("async"),
+            TokenKind::PyAwait => Some("await"),
+            TokenKind::PyBreak => Some("break"),
+            TokenKind::PyClass => Some("class"),
+            TokenKind::PyContinue => Some("continue"),
+            TokenKind::PyDef => Some("def"),
+            TokenKind::PyDel => Some("del"),
+            TokenKind::PyElif => Some("elif"),
+            TokenKind::PyElse => Some("else"),
+            TokenKind::PyExcept => Some("except"),
+            TokenKind::PyFinally => Some("finally"),
+            TokenKind::PyFor => Some("for"),
+            TokenKind::PyFrom => Some("from"),
+            TokenKind::PyGlobal => Some("global"),
+            TokenKind::PyIf => Some("if"),
+            TokenKind::PyImport => Some("import"),
+            TokenKind::PyIn => Some("in"),
+            TokenKind::PyIs => Some("is"),
+            TokenKind::PyLambda => Some("lambda"),
+            TokenKind::PyNonlocal => Some("nonlocal"),
+            TokenKind::PyNot => Some("not"),
+            TokenKind::PyOr => Some("or"),
+            TokenKind::PyPass => Some("pass"),
+            TokenKind::PyRaise => Some("raise"),
+            TokenKind::PyReturn => Some("return"),
+            TokenKind::PyTry => Some("try"),
+            TokenKind::PyWhile => Some("while"),
+            TokenKind::PyWith => Some("with"),
+            TokenKind::PyYield => Some("yield"),
+            TokenKind::PyMatch => Some("match"),
+            TokenKind::PyCase => Some("case"),
+            TokenKind::PyType => Some("type"),
+            TokenKind::PyUnderscore => Some("_"),
+            TokenKind::PyPlus => Some("+"),
+            TokenKind::PyMinus => Some("-"),
+            TokenKind::PyMul => Some("*"),
+            TokenKind::PyPower => Some("**"),
+            TokenKind::PyDiv => Some("/"),
+            TokenKind::PyFloorDiv => Some("//"),
+            TokenKind::PyModulo => Some("%"),
+            TokenKind::PyMatrices => Some("@"),
+            TokenKind::PyShiftLeft => Some("<<"),
+            TokenKind::PyShiftRight => Some(">>"),
+            TokenKind::PyBitAnd => Some("&"),
+            TokenKind::PyBitOr => Some("|"),
+            TokenKind::PyBitXor => Some("^"),
+            TokenKind::PyBitInvert => Some("~"),
+            TokenKind::PyColonAssign => Some(":="),
+            TokenKind::PyLess => Some("<"),
+            TokenKind::PyGreater => Some(">"),
+            TokenKind::PyLessEqual => Some("<="),
+            TokenKind::PyGreaterEqual => Some(">="),
+            TokenKind::PyEqual => Some("=="),
+            TokenKind::PyNotEqual => Some("!="),
+            TokenKind::PyExclamation => Some("!"),
+            TokenKind::PyLeftParen => Some("("),
+            TokenKind::PyRightParen => Some(")"),
+            TokenKind::PyLeftBracket => Some("["),
+            TokenKind::PyRightBracket => Some("]"),
+            TokenKind::PyLeftCurly => Some("{"),
+            TokenKind::PyRightCurly => Some("}"),
+            TokenKind::PyComma => Some(","),
+            TokenKind::PyColon => Some(":"),
+            TokenKind::PyDot => Some("."),
+            TokenKind::PyEllipsis => Some("..."),
+            TokenKind::PySemiColon => Some(";"),
+            TokenKind::PyAssign => Some("="),
+            TokenKind::PyArrow => Some("->"),
+            TokenKind::PyPlusAssign => Some("+="),
+            TokenKind::PyMinusAssign => Some("-="),
+            TokenKind::PyMulAssign => Some("*="),
+            TokenKind::PyDivAssign => Some("/="),
+            TokenKind::PyFloorDivAssign => Some("//="),
+            TokenKind::PyModuloAssign => Some("%="),
+            TokenKind::PyMatricesAssign => Some("@="),
+            TokenKind::PyBitAndAssign => Some("&="),
+            TokenKind::PyBitOrAssign => Some("|="),
+            TokenKind::PyBitXorAssign => Some("^="),
+            TokenKind::PyShiftLeftAssign => Some("<<="),
+            TokenKind::PyShiftRightAssign => Some(">>="),
+            TokenKind::PyPowerAssign => Some("**="),
+            _ => None
+        }
+    }
+
+    // Name of token type in the tokenize module of CPython, where keywords are names.
+    pub fn type_name(self) -> &'static str {
+        match self {
+            TokenKind::PyEof => "ENDMARKER",
+            TokenKind::PyNewline => "NEWLINE",
+            TokenKind::PyNl => "NL",
+            TokenKind::PyIndent => "INDENT",
+            TokenKind::PyDedent => "DEDENT",
+            TokenKind::PyFalse |
+            TokenKind::PyNone |
+            TokenKind::PyTrue |
+            TokenKind::PyAnd |
+            TokenKind::PyAs |
+            TokenKind::PyAssert |
+            TokenKind::PyAsync |
+            TokenKind::PyAwait |
+            TokenKind::PyBreak |
+            TokenKind::PyClass |
+            TokenKind::PyContinue |
+            TokenKind::PyDef |
+            TokenKind::PyDel |
+            TokenKind::PyElif |
+            TokenKind::PyElse |
+            TokenKind::PyExcept |
+            TokenKind::PyFinally |
+            TokenKind::PyFor |
+            TokenKind::PyFrom |
+            TokenKind::PyGlobal |
+            TokenKind::PyIf |
+            TokenKind::PyImport |
+            TokenKind::PyIn |
+            TokenKind::PyIs |
+            TokenKind::PyLambda |
+            TokenKind::PyNonlocal |
+            TokenKind::PyNot |
+            TokenKind::PyOr |
+            TokenKind::PyPass |
+            TokenKind::PyRaise |
+            TokenKind::PyReturn |
+            TokenKind::PyTry |
+            TokenKind::PyWhile |
+            TokenKind::PyWith |
+            TokenKind::PyYield |
+            TokenKind::PyMatch |
+            TokenKind::PyCase |
+            TokenKind::PyType |
+            TokenKind::PyUnderscore |
+            TokenKind::PyName => "NAME",
+            TokenKind::PyNumber => "NUMBER",
+            TokenKind::PyString => "STRING",
+            TokenKind::PyFStringStart => "FSTRING_START",
+            TokenKind::PyFStringMiddle => "FSTRING_MIDDLE",
+            TokenKind::PyFStringEnd => "FSTRING_END",
+            TokenKind::PyTypeComment => "TYPE_COMMENT",
+            TokenKind::PyComment => "COMMENT",
+            TokenKind::PyError => "ERRORTOKEN",
+            _ => "OP"
+        }
+    }
+
+    // Reserved keywords, which are never names.
+    pub fn is_keyword(self) -> bool {
+        matches!(self,
+            TokenKind::PyFalse | TokenKind::PyNone | TokenKind::PyTrue | TokenKind::PyAnd |
+            TokenKind::PyAs | TokenKind::PyAssert | TokenKind::PyAsync | TokenKind::PyAwait |
+            TokenKind::PyBreak | TokenKind::PyClass | TokenKind::PyContinue | TokenKind::PyDef |
+            TokenKind::PyDel | TokenKind::PyElif | TokenKind::PyElse | TokenKind::PyExcept |
+            TokenKind::PyFinally | TokenKind::PyFor | TokenKind::PyFrom | TokenKind::PyGlobal |
+            TokenKind::PyIf | TokenKind::PyImport | TokenKind::PyIn | TokenKind::PyIs |
+            TokenKind::PyLambda | TokenKind::PyNonlocal | TokenKind::PyNot | TokenKind::PyOr |
+            TokenKind::PyPass | TokenKind::PyRaise | TokenKind::PyReturn | TokenKind::PyTry |
+            TokenKind::PyWhile | TokenKind::PyWith | TokenKind::PyYield
+        )
+    }
+
+    // Soft keywords, which are names outside of match statements, case blocks and type aliases.
+    pub fn is_soft_keyword(self) -> bool {
+        matches!(self, TokenKind::PyMatch | TokenKind::PyCase | TokenKind::PyType | TokenKind::PyUnderscore)
+    }
+
+    // Operators and delimiters, which are the tokens of type OP in CPython.
+    pub fn is_operator(self) -> bool {
+        self.spelling().is_some() && !self.is_keyword() && !self.is_soft_keyword()
+    }
+
+    // Augmented assignments, like '+=' and '**='.
+    pub fn is_augmented_assign(self) -> bool {
+        matches!(self,
+            TokenKind::PyPlusAssign | TokenKind::PyMinusAssign | TokenKind::PyMulAssign |
+            TokenKind::PyDivAssign | TokenKind::PyFloorDivAssign | TokenKind::PyModuloAssign |
+            TokenKind::PyMatricesAssign | TokenKind::PyBitAndAssign | TokenKind::PyBitOrAssign |
+            TokenKind::PyBitXorAssign | TokenKind::PyShiftLeftAssign | TokenKind::PyShiftRightAssign |
+            TokenKind::PyPowerAssign
+        )
+    }
+
+    // Numbers, strings and the parts of f-strings, while True, False and None are keywords.
+    pub fn is_literal(self) -> bool {
+        matches!(self, TokenKind::PyNumber | TokenKind::PyString | TokenKind::PyFStringStart | TokenKind::PyFStringMiddle | TokenKind::PyFStringEnd)
+    }
+
+    pub fn is_bracket_open(self) -> bool {
+        matches!(self, TokenKind::PyLeftParen | TokenKind::PyLeftBracket | TokenKind::PyLeftCurly)
+    }
+
+    pub fn is_bracket_close(self) -> bool {
+        matches!(self, TokenKind::PyRightParen | TokenKind::PyRightBracket | TokenKind::PyRightCurly)
+    }
+
+    // Class of token for syntax highlighting. Operators compute values, while the other operators
+    // and delimiters, such as brackets, '=', '->' and augmented assignments, are delimiters.
+    pub fn category(self) -> TokenCategory {
+        match self {
+            _ if self.is_keyword() => TokenCategory::Keyword,
+            _ if self.is_soft_keyword() => TokenCategory::SoftKeyword,
+            TokenKind::PyName => TokenCategory::Name,
+            TokenKind::PyNumber => TokenCategory::Number,
+            TokenKind::PyString | TokenKind::PyFStringStart | TokenKind::PyFStringMiddle | TokenKind::PyFStringEnd => TokenCategory::String,
+            TokenKind::PyComment | TokenKind::PyTypeComment => TokenCategory::Comment,
+            TokenKind::PyPlus | TokenKind::PyMinus | TokenKind::PyMul | TokenKind::PyPower |
+            TokenKind::PyDiv | TokenKind::PyFloorDiv | TokenKind::PyModulo | TokenKind::PyMatrices |
+            TokenKind::PyShiftLeft | TokenKind::PyShiftRight | TokenKind::PyBitAnd |
+            TokenKind::PyBitOr | TokenKind::PyBitXor | TokenKind::PyBitInvert |
+            TokenKind::PyColonAssign | TokenKind::PyLess | TokenKind::PyGreater |
+            TokenKind::PyLessEqual | TokenKind::PyGreaterEqual | TokenKind::PyEqual |
+            TokenKind::PyNotEqual => TokenCategory::Operator,
+            _ if self.is_operator() => TokenCategory::Delimiter,
+            TokenKind::PyError => TokenCategory::Error,
+            _ => TokenCategory::Layout
+        }
+    }
+}
+
+impl<T> TokenSymbol<T> {
+    // Offsets of start and end of token in source buffer.
+    pub fn offsets(&self) -> (u32, u32) {
+        match self {
+            TokenSymbol::PyEof(start, end) |
+            TokenSymbol::PyNewline(start, end) |
+            TokenSymbol::PyNl(start, end) |
+            TokenSymbol::PyIndent(start, end) |
+            TokenSymbol::PyDedent(start, end) |
+            TokenSymbol::PyFalse(start, end) |
+            TokenSymbol::PyNone(start, end) |
+            TokenSymbol::PyTrue(start, end) |
+            TokenSymbol::PyAnd(start, end) |
+            TokenSymbol::PyAs(start, end) |
+            TokenSymbol::PyAssert(start, end) |
+            TokenSymbol::PyAsync(start, end) |
+            TokenSymbol::PyAwait(start, end) |
+            TokenSymbol::PyBreak(start, end) |
+            TokenSymbol::PyClass(start, end) |
+            TokenSymbol::PyContinue(start, end) |
+            TokenSymbol::PyDef(start, end) |
+            TokenSymbol::PyDel(start, end) |
+            TokenSymbol::PyElif(start, end) |
+            TokenSymbol::PyElse(start, end) |
+            TokenSymbol::PyExcept(start, end) |
+            TokenSymbol::PyFinally(start, end) |
+            TokenSymbol::PyFor(start, end) |
+            TokenSymbol::PyFrom(start, end) |
+            TokenSymbol::PyGlobal(start, end) |
+            TokenSymbol::PyIf(start, end) |
+            TokenSymbol::PyImport(start, end) |
+            TokenSymbol::PyIn(start, end) |
+            TokenSymbol::PyIs(start, end) |
+            TokenSymbol::PyLambda(start, end) |
+            TokenSymbol::PyNonlocal(start, end) |
+            TokenSymbol::PyNot(start, end) |
+            TokenSymbol::PyOr(start, end) |
+            TokenSymbol::PyPass(start, end) |
+            TokenSymbol::PyRaise(start, end) |
+            TokenSymbol::PyReturn(start, end) |
+            TokenSymbol::PyTry(start, end) |
+            TokenSymbol::PyWhile(start, end) |
+            TokenSymbol::PyWith(start, end) |
+            TokenSymbol::PyYield(start, end) |
+            TokenSymbol::PyMatch(start, end) |
+            TokenSymbol::PyCase(start, end) |
+            TokenSymbol::PyType(start, end) |
+            TokenSymbol::PyUnderscore(start, end) |
+            TokenSymbol::PyPlus(start, end) |
+            TokenSymbol::PyMinus(start, end) |
+            TokenSymbol::PyMul(start, end) |
+            TokenSymbol::PyPower(start, end) |
+            TokenSymbol::PyDiv(start, end) |
+            TokenSymbol::PyFloorDiv(start, end) |
+            TokenSymbol::PyModulo(start, end) |
+            TokenSymbol::PyMatrices(start, end) |
+            TokenSymbol::PyShiftLeft(start, end) |
+            TokenSymbol::PyShiftRight(start, end) |
+            TokenSymbol::PyBitAnd(start, end) |
+            TokenSymbol::PyBitOr(start, end) |
+            TokenSymbol::PyBitXor(start, end) |
+            TokenSymbol::PyBitInvert(start, end) |
+            TokenSymbol::PyColonAssign(start, end) |
+            TokenSymbol::PyLess(start, end) |
+            TokenSymbol::PyGreater(start, end) |
+            TokenSymbol::PyLessEqual(start, end) |
+            TokenSymbol::PyGreaterEqual(start, end) |
+            TokenSymbol::PyEqual(start, end) |
+            TokenSymbol::PyNotEqual(start, end) |
+            TokenSymbol::PyExclamation(start, end) |
+            TokenSymbol::PyLeftParen(start, end) |
+            TokenSymbol::PyRightParen(start, end) |
+            TokenSymbol::PyLeftBracket(start, end) |
+            TokenSymbol::PyRightBracket(start, end) |
+            TokenSymbol::PyLeftCurly(start, end) |
+            TokenSymbol::PyRightCurly(start, end) |
+            TokenSymbol::PyComma(start, end) |
+            TokenSymbol::PyColon(start, end) |
+            TokenSymbol::PyDot(start, end) |
+            TokenSymbol::PyEllipsis(start, end) |
+            TokenSymbol::PySemiColon(start, end) |
+            TokenSymbol::PyAssign(start, end) |
+            TokenSymbol::PyArrow(start, end) |
+            TokenSymbol::PyPlusAssign(start, end) |
+            TokenSymbol::PyMinusAssign(start, end) |
+            TokenSymbol::PyMulAssign(start, end) |
+            TokenSymbol::PyDivAssign(start, end) |
+            TokenSymbol::PyFloorDivAssign(start, end) |
+            TokenSymbol::PyModuloAssign(start, end) |
+            TokenSymbol::PyMatricesAssign(start, end) |
+            TokenSymbol::PyBitAndAssign(start, end) |
+            TokenSymbol::PyBitOrAssign(start, end) |
+            TokenSymbol::PyBitXorAssign(start, end) |
+            TokenSymbol::PyShiftLeftAssign(start, end) |
+            TokenSymbol::PyShiftRightAssign(start, end) |
+            TokenSymbol::PyPowerAssign(start, end) |
+            TokenSymbol::PyName(start, end, _) |
+            TokenSymbol::PyNumber(start, end, _) |
+            TokenSymbol::PyString(start, end, _) |
+            TokenSymbol::PyFStringStart(start, end, _) |
+            TokenSymbol::PyFStringMiddle(start, end, _) |
+            TokenSymbol::PyFStringEnd(start, end, _) |
+            TokenSymbol::PyTypeComment(start, end, _) |
+            TokenSymbol::PyComment(start, end, _) |
+            TokenSymbol::PyError(start, end, _) => ( *start, *end )
+        }
+    }
+
+    // Offsets of token for moving it, when source text in front of it has been edited.
+    pub(crate) fn offsets_mut(&mut self) -> (&mut u32, &mut u32) {
+        match self {
+            TokenSymbol::PyEof(start, end) |
+            TokenSymbol::PyNewline(start, end) |
+            TokenSymbol::PyNl(start, end) |
+            TokenSymbol::PyIndent(start, end) |
+            TokenSymbol::PyDedent(start, end) |
+            TokenSymbol::PyFalse(start, end) |
+            TokenSymbol::PyNone(start, end) |
+            TokenSymbol::PyTrue(start, end) |
+            TokenSymbol::PyAnd(start, end) |
+            TokenSymbol::PyAs(start, end) |
+            TokenSymbol::PyAssert(start, end) |
+            TokenSymbol::PyAsync(start, end) |
+            TokenSymbol::PyAwait(start, end) |
+            TokenSymbol::PyBreak(start, end) |
+            TokenSymbol::PyClass(start, end) |
+            TokenSymbol::PyContinue(start, end) |
+            TokenSymbol::PyDef(start, end) |
+            TokenSymbol::PyDel(start, end) |
+            TokenSymbol::PyElif(start, end) |
+            TokenSymbol::PyElse(start, end) |
+            TokenSymbol::PyExcept(start, end) |
+            TokenSymbol::PyFinally(start, end) |
+            TokenSymbol::PyFor(start, end) |
+            TokenSymbol::PyFrom(start, end) |
+            TokenSymbol::PyGlobal(start, end) |
+            TokenSymbol::PyIf(start, end) |
+            TokenSymbol::PyImport(start, end) |
+            TokenSymbol::PyIn(start, end) |
+            TokenSymbol::PyIs(start, end) |
+            TokenSymbol::PyLambda(start, end) |
+            TokenSymbol::PyNonlocal(start, end) |
+            TokenSymbol::PyNot(start, end) |
+            TokenSymbol::PyOr(start, end) |
+            TokenSymbol::PyPass(start, end) |
+            TokenSymbol::PyRaise(start, end) |
+            TokenSymbol::PyReturn(start, end) |
+            TokenSymbol::PyTry(start, end) |
+            TokenSymbol::PyWhile(start, end) |
+            TokenSymbol::PyWith(start, end) |
+            TokenSymbol::PyYield(start, end) |
+            TokenSymbol::PyMatch(start, end) |
+            TokenSymbol::PyCase(start, end) |
+            TokenSymbol::PyType(start, end) |
+            TokenSymbol::PyUnderscore(start, end) |
+            TokenSymbol::PyPlus(start, end) |
+            TokenSymbol::PyMinus(start, end) |
+            TokenSymbol::PyMul(start, end) |
+            TokenSymbol::PyPower(start, end) |
+            TokenSymbol::PyDiv(start, end) |
+            TokenSymbol::PyFloorDiv(start, end) |
+            TokenSymbol::PyModulo(start, end) |
+            TokenSymbol::PyMatrices(start, end) |
+            TokenSymbol::PyShiftLeft(start, end) |
+            TokenSymbol::PyShiftRight(start, end) |
+            TokenSymbol::PyBitAnd(start, end) |
+            TokenSymbol::PyBitOr(start, end) |
+            TokenSymbol::PyBitXor(start, end) |
+            TokenSymbol::PyBitInvert(start, end) |
+            TokenSymbol::PyColonAssign(start, end) |
+            TokenSymbol::PyLess(start, end) |
+            TokenSymbol::PyGreater(start, end) |
+            TokenSymbol::PyLessEqual(start, end) |
+            TokenSymbol::PyGreaterEqual(start, end) |
+            TokenSymbol::PyEqual(start, end) |
+            TokenSymbol::PyNotEqual(start, end) |
+            TokenSymbol::PyExclamation(start, end) |
+            TokenSymbol::PyLeftParen(start, end) |
+            TokenSymbol::PyRightParen(start, end) |
+            TokenSymbol::PyLeftBracket(start, end) |
+            TokenSymbol::PyRightBracket(start, end) |
+            TokenSymbol::PyLeftCurly(start, end) |
+            TokenSymbol::PyRightCurly(start, end) |
+            TokenSymbol::PyComma(start, end) |
+            TokenSymbol::PyColon(start, end) |
+            TokenSymbol::PyDot(start, end) |
+            TokenSymbol::PyEllipsis(start, end) |
+            TokenSymbol::PySemiColon(start, end) |
+            TokenSymbol::PyAssign(start, end) |
+            TokenSymbol::PyArrow(start, end) |
+            TokenSymbol::PyPlusAssign(start, end) |
+            TokenSymbol::PyMinusAssign(start, end) |
+            TokenSymbol::PyMulAssign(start, end) |
+            TokenSymbol::PyDivAssign(start, end) |
+            TokenSymbol::PyFloorDivAssign(start, end) |
+            TokenSymbol::PyModuloAssign(start, end) |
+            TokenSymbol::PyMatricesAssign(start, end) |
+            TokenSymbol::PyBitAndAssign(start, end) |
+            TokenSymbol::PyBitOrAssign(start, end) |
+            TokenSymbol::PyBitXorAssign(start, end) |
+            TokenSymbol::PyShiftLeftAssign(start, end) |
+            TokenSymbol::PyShiftRightAssign(start, end) |
+            TokenSymbol::PyPowerAssign(start, end) |
+            TokenSymbol::PyName(start, end, _) |
+            TokenSymbol::PyNumber(start, end, _) |
+            TokenSymbol::PyString(start, end, _) |
+            TokenSymbol::PyFStringStart(start, end, _) |
+            TokenSymbol::PyFStringMiddle(start, end, _) |
+            TokenSymbol::PyFStringEnd(start, end, _) |
+            TokenSymbol::PyTypeComment(start, end, _) |
+            TokenSymbol::PyComment(start, end, _) |
+            TokenSymbol::PyError(start, end, _) => ( start, end )
+        }
+    }
+
+    // Kind of token, the variant without its fields.
+    pub fn kind(&self) -> TokenKind {
+        match self {
+            TokenSymbol::PyEof(_, _) => TokenKind::PyEof,
+            TokenSymbol::PyNewline(_, _) => TokenKind::PyNewline,
+            TokenSymbol::PyNl(_, _) => TokenKind::PyNl,
+            TokenSymbol::PyIndent(_, _) => TokenKind::PyIndent,
+            TokenSymbol::PyDedent(_, _) => TokenKind::PyDedent,
+            TokenSymbol::PyFalse(_, _) => TokenKind::PyFalse,
+            TokenSymbol::PyNone(_, _) => TokenKind::PyNone,
+            TokenSymbol::PyTrue(_, _) => TokenKind::PyTrue,
+            TokenSymbol::PyAnd(_, _) => TokenKind::PyAnd,
+            TokenSymbol::PyAs(_, _) => TokenKind::PyAs,
+            TokenSymbol::PyAssert(_, _) => TokenKind::PyAssert,
+            TokenSymbol::PyAsync(_, _) => TokenKind::PyAsync,
+            TokenSymbol::PyAwait(_, _) => TokenKind::PyAwait,
+            TokenSymbol::PyBreak(_, _) => TokenKind::PyBreak,
+            TokenSymbol::PyClass(_, _) => TokenKind::PyClass,
+            TokenSymbol::PyContinue(_, _) => TokenKind::PyContinue,
+            TokenSymbol::PyDef(_, _) => TokenKind::PyDef,
+            TokenSymbol::PyDel(_, _) => TokenKind::PyDel,
+            TokenSymbol::PyElif(_, _) => TokenKind::PyElif,
+            TokenSymbol::PyElse(_, _) => TokenKind::PyElse,
+            TokenSymbol::PyExcept(_, _) => TokenKind::PyExcept,
+            TokenSymbol::PyFinally(_, _) => TokenKind::PyFinally,
+            TokenSymbol::PyFor(_, _) => TokenKind::PyFor,
+            TokenSymbol::PyFrom(_, _) => TokenKind::PyFrom,
+            TokenSymbol::PyGlobal(_, _) => TokenKind::PyGlobal,
+            TokenSymbol::PyIf(_, _) => TokenKind::PyIf,
+            TokenSymbol::PyImport(_, _) => TokenKind::PyImport,
+            TokenSymbol::PyIn(_, _) => TokenKind::PyIn,
+            TokenSymbol::PyIs(_, _) => TokenKind::PyIs,
+            TokenSymbol::PyLambda(_, _) => TokenKind::PyLambda,
+            TokenSymbol::PyNonlocal(_, _) => TokenKind::PyNonlocal,
+            TokenSymbol::PyNot(_, _) => TokenKind::PyNot,
+            TokenSymbol::PyOr(_, _) => TokenKind::PyOr,
+            TokenSymbol::PyPass(_, _) => TokenKind::PyPass,
+            TokenSymbol::PyRaise(_, _) => TokenKind::PyRaise,
+            TokenSymbol::PyReturn(_, _) => TokenKind::PyReturn,
+            TokenSymbol::PyTry(_, _) => TokenKind::PyTry,
+            TokenSymbol::PyWhile(_, _) => TokenKind::PyWhile,
+            TokenSymbol::PyWith(_, _) => TokenKind::PyWith,
+            TokenSymbol::PyYield(_, _) => TokenKind::PyYield,
+            TokenSymbol::PyMatch(_, _) => TokenKind::PyMatch,
+            TokenSymbol::PyCase(_, _) => TokenKind::PyCase,
+            TokenSymbol::PyType(_, _) => TokenKind::PyType,
+            TokenSymbol::PyUnderscore(_, _) => TokenKind::PyUnderscore,
+            TokenSymbol::PyPlus(_, _) => TokenKind::PyPlus,
+            TokenSymbol::PyMinus(_, _) => TokenKind::PyMinus,
+            TokenSymbol::PyMul(_, _) => TokenKind::PyMul,
+            TokenSymbol::PyPower(_, _) => TokenKind::PyPower,
+            TokenSymbol::PyDiv(_, _) => TokenKind::PyDiv,
+            TokenSymbol::PyFloorDiv(_, _) => TokenKind::PyFloorDiv,
+            TokenSymbol::PyModulo(_, _) => TokenKind::PyModulo,
+            TokenSymbol::PyMatrices(_, _) => TokenKind::PyMatrices,
+            TokenSymbol::PyShiftLeft(_, _) => TokenKind::PyShiftLeft,
+            TokenSymbol::PyShiftRight(_, _) => TokenKind::PyShiftRight,
+            TokenSymbol::PyBitAnd(_, _) => TokenKind::PyBitAnd,
+            TokenSymbol::PyBitOr(_, _) => TokenKind::PyBitOr,
+            TokenSymbol::PyBitXor(_, _) => TokenKind::PyBitXor,
+            TokenSymbol::PyBitInvert(_, _) => TokenKind::PyBitInvert,
+            TokenSymbol::PyColonAssign(_, _) => TokenKind::PyColonAssign,
+            TokenSymbol::PyLess(_, _) => TokenKind::PyLess,
+            TokenSymbol::PyGreater(_, _) => TokenKind::PyGreater,
+            TokenSymbol::PyLessEqual(_, _) => TokenKind::PyLessEqual,
+            TokenSymbol::PyGreaterEqual(_, _) => TokenKind::PyGreaterEqual,
+            TokenSymbol::PyEqual(_, _) => TokenKind::PyEqual,
+            TokenSymbol::PyNotEqual(_, _) => TokenKind::PyNotEqual,
+            TokenSymbol::PyExclamation(_, _) => TokenKind::PyExclamation,
+            TokenSymbol::PyLeftParen(_, _) => TokenKind::PyLeftParen,
+            TokenSymbol::PyRightParen(_, _) => TokenKind::PyRightParen,
+            TokenSymbol::PyLeftBracket(_, _) => TokenKind::PyLeftBracket,
+            TokenSymbol::PyRightBracket(_, _) => TokenKind::PyRightBracket,
+            TokenSymbol::PyLeftCurly(_, _) => TokenKind::PyLeftCurly,
+            TokenSymbol::PyRightCurly(_, _) => TokenKind::PyRightCurly,
+            TokenSymbol::PyComma(_, _) => TokenKind::PyComma,
+            TokenSymbol::PyColon(_, _) => TokenKind::PyColon,
+            TokenSymbol::PyDot(_, _) => TokenKind::PyDot,
+            TokenSymbol::PyEllipsis(_, _) => TokenKind::PyEllipsis,
+            TokenSymbol::PySemiColon(_, _) => TokenKind::PySemiColon,
+            TokenSymbol::PyAssign(_, _) => TokenKind::PyAssign,
+            TokenSymbol::PyArrow(_, _) => TokenKind::PyArrow,
+            TokenSymbol::PyPlusAssign(_, _) => TokenKind::PyPlusAssign,
+            TokenSymbol::PyMinusAssign(_, _) => TokenKind::PyMinusAssign,
+            TokenSymbol::PyMulAssign(_, _) => TokenKind::PyMulAssign,
+            TokenSymbol::PyDivAssign(_, _) => TokenKind::PyDivAssign,
+            TokenSymbol::PyFloorDivAssign(_, _) => TokenKind::PyFloorDivAssign,
+            TokenSymbol::PyModuloAssign(_, _) => TokenKind::PyModuloAssign,
+            TokenSymbol::PyMatricesAssign(_, _) => TokenKind::PyMatricesAssign,
+            TokenSymbol::PyBitAndAssign(_, _) => TokenKind::PyBitAndAssign,
+            TokenSymbol::PyBitOrAssign(_, _) => TokenKind::PyBitOrAssign,
+            TokenSymbol::PyBitXorAssign(_, _) => TokenKind::PyBitXorAssign,
+            TokenSymbol::PyShiftLeftAssign(_, _) => TokenKind::PyShiftLeftAssign,
+            TokenSymbol::PyShiftRightAssign(_, _) => TokenKind::PyShiftRightAssign,
+            TokenSymbol::PyPowerAssign(_, _) => TokenKind::PyPowerAssign,
+            TokenSymbol::PyName(_, _, _) => TokenKind::PyName,
+            TokenSymbol::PyNumber(_, _, _) => TokenKind::PyNumber,
+            TokenSymbol::PyString(_, _, _) => TokenKind::PyString,
+            TokenSymbol::PyFStringStart(_, _, _) => TokenKind::PyFStringStart,
+            TokenSymbol::PyFStringMiddle(_, _, _) => TokenKind::PyFStringMiddle,
+            TokenSymbol::PyFStringEnd(_, _, _) => TokenKind::PyFStringEnd,
+            TokenSymbol::PyTypeComment(_, _, _) => TokenKind::PyTypeComment,
+            TokenSymbol::PyComment(_, _, _) => TokenKind::PyComment,
+            TokenSymbol::PyError(_, _, _) => TokenKind::PyError
+        }
+    }
+
+    pub fn is_keyword(&self) -> bool {
+        self.kind().is_keyword()
+    }
+
+    pub fn is_soft_keyword(&self) -> bool {
+        self.kind().is_soft_keyword()
+    }
+
+    pub fn is_operator(&self) -> bool {
+        self.kind().is_operator()
+    }
+
+    pub fn is_augmented_assign(&self) -> bool {
+        self.kind().is_augmented_assign()
+    }
+
+    pub fn is_literal(&self) -> bool {
+        self.kind().is_literal()
+    }
+
+    pub fn is_bracket_open(&self) -> bool {
+        self.kind().is_bracket_open()
+    }
+
+    pub fn is_bracket_close(&self) -> bool {
+        self.kind().is_bracket_close()
+    }
+
+    // Class of token for syntax highlighting.
+    pub fn category(&self) -> TokenCategory {
+        self.kind().category()
+    }
+}
+
+impl<T> From<&TokenSymbol<T>> for TokenKind {
+    fn from(symbol: &TokenSymbol<T>) -> Self {
+        symbol.kind()
+    }
+}
+
+// Source text of token, with concatenated strings as written and line breaks as '\r\n' or '\n' by their
+// width, empty for the PyNewline ending a source without a final line break. Names are NFKC normalized
+// as held, type comments are shown as their type, and PyIndent, PyDedent and PyEof are empty.
+impl<T: AsRef<str>> fmt::Display for TokenSymbol<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(spelling) = self.kind().spelling() {
+            return write!(f, "{}", spelling)
+        }
+        match self {
+            TokenSymbol::PyNewline(start, end) | TokenSymbol::PyNl(start, end) => match end - start {
+                0 => Ok(()),
+                2 => f.write_str("\r\n"),
+                _ => f.write_str("\n")
+            },
+            TokenSymbol::PyString(_, _, parts) => {
+                for part in parts.iter() {
+                    f.write_str(part.as_ref())?
+                }
+                Ok(())
+            },
+            TokenSymbol::PyName(_, _, text) |
+            TokenSymbol::PyNumber(_, _, text) |
+            TokenSymbol::PyFStringStart(_, _, text) |
+            TokenSymbol::PyFStringMiddle(_, _, text) |
+            TokenSymbol::PyFStringEnd(_, _, text) |
+            TokenSymbol::PyTypeComment(_, _, text) |
+            TokenSymbol::PyComment(_, _, text) |
+            TokenSymbol::PyError(_, _, text) => write!(f, "{}", text.as_ref()),
+            _ => Ok(())
+        }
+    }
+}
+
+// Token type, quoted spelling and offsets, like output of the tokenize module of CPython but with offsets
+// in place of line and column, as a token holds no line index. Only debug_tokens() of the tokenizer, which
+// gives each token as Token, shows line and column as in 'OP '**=' 1:4-1:7'.
+impl<T: AsRef<str>> fmt::Debug for TokenSymbol<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ( start, end ) = self.offsets();
+        write!(f, "{} {} {}-{}", self.kind().type_name(), python_repr(&self.to_string()), start, end)
+    }
+}
+
+impl TokenSymbol {
+    // Soft keywords are names in all other places than their own statements, so parser can take them
+    // as a name when not starting a match statement, case block or type alias.
+    pub fn as_name(&self) -> Option<TokenSymbol> {
+        match self {
+            TokenSymbol::PyMatch(start, end) => Some(TokenSymbol::PyName(*start, *end, Box::from("match"))),
+            TokenSymbol::PyCase(start, end) => Some(TokenSymbol::PyName(*start, *end, Box::from("case"))),
+            TokenSymbol::PyType(start, end) => Some(TokenSymbol::PyName(*start, *end, Box::from("type"))),
+            TokenSymbol::PyUnderscore(start, end) => Some(TokenSymbol::PyName(*start, *end, Box::from("_"))),
+            TokenSymbol::PyName(_, _, _) => Some(self.clone()),
+            _ => None
+        }
+    }
+}
+
+// Token from lossless tokenizing, with whitespace, comments and line continuations before it as trivia
+// and its exact source text, so that concatenating trivia and text of all tokens gives back the source.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct LosslessToken {
+    pub trivia: Box<str>,
+    pub text: Box<str>,
+    pub symbol: TokenSymbol
+}
+
+// Token with kind, line and column span and text, read the same way for all kinds of tokens. Text is
+// the source text of the token, except for names which hold the NFKC normalized name as in PyName.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, PartialEq, Eq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+    pub text: Box<str>
+}
+
+// Token as given by the tokenize module of CPython, with type name, source text, start and end as line
+// and column, and the physical lines of source the token is found on.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TokenInfo {
+    #[cfg_attr(feature = "serde", serde(rename = "type"))]
+    pub type_name: Box<str>,
+    pub string: Box<str>,
+    pub start: (u32, u32),
+    pub end: (u32, u32),
+    pub line: Box<str>
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+// Token type, quoted text and line and column span, like output of the tokenize module of CPython.
+impl fmt::Debug for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}:{}-{}:{}", self.kind.type_name(), python_repr(&self.text),
+            self.span.start.line, self.span.start.column, self.span.end.line, self.span.end.column)
+    }
+}
+
+// Text quoted as by repr() in Python.
+fn python_repr(text: &str) -> String {
+    let quote = if text.contains('\'') && !text.contains('"') { '"' } else { '\'' };
+    let mut repr = String::with_capacity(text.len() + 2);
+    repr.push(quote);
+    for c in text.chars() {
+        match c {
+            '\\' => repr.push_str("\\\\"),
+            '\n' => repr.push_str("\\n"),
+            '\r' => repr.push_str("\\r"),
+            '\t' => repr.push_str("\\t"),
+            _ if c == quote => {
+                repr.push('\\');
+                repr.push(c)
+            },
+            _ if c.is_control() && ( c as u32 ) < 0x100 => repr.push_str(&format!("\\x{:02x}", c as u32)),
+            _ => repr.push(c)
+        }
+    }
+    repr.push(quote);
+    repr
+}
@@ -1,142 +1,1561 @@
 
-use crate::parser::token::TokenSymbol;
+use std::borrow::Borrow;
+use std::collections::VecDeque;
+use std::iter::FusedIterator;
+use unicode_ident::{is_xid_continue, is_xid_start};
+use crate::parser::encoding::decode_source;
+use crate::parser::error::TokenizerError;
+use crate::parser::literal::{decode_literal, LiteralValue, LiteralWarning};
+use crate::parser::location::{LineIndex, Position, Span};
+use crate::parser::source::SourceBuffer;
+use crate::parser::token::{LosslessToken, Token, TokenInfo, TokenSymbol};
+use crate::parser::version::PythonVersion;
+
+// Deepest nesting of indented blocks and of brackets accepted, same limits as CPython.
+const MAX_INDENT_LEVEL: usize = 100;
+const MAX_PAREN_LEVEL: usize = 200;
 
 // Data structure for object ///////////////////////////////////////////////////////////////////////
 
-pub struct PythonCoreTokenizer {
-    source_buffer: Box<Vec<char>>,
+pub struct PythonCoreTokenizer<S: SourceBuffer = Vec<char>> {
+    source_buffer: S,
+    line_index: LineIndex,
     index: u32,
-    tab_size: u8
+    tab_size: u8,
+    type_comments: bool,
+    comments: bool,
+    non_logical_newlines: bool,
+    error_recovery: bool,
+    python_version: PythonVersion,
+    brackets: Vec<(char, u32)>,
+    line_has_tokens: bool,
+    at_line_start: bool,
+    indent_stack: Vec<u32>,
+    alt_indent_stack: Vec<u32>,
+    pending: VecDeque<TokenSymbol<S::Text>>,
+    fstring_stack: Vec<FStringMode>,
+    token_start: u32,
+    diagnostics: Vec<TokenizerError>
+}
+
+// State of an f-string being scanned, pushed at PyFStringStart and popped at PyFStringEnd.
+struct FStringMode {
+    quote: char,
+    is_triple: bool,
+    is_raw: bool,
+    start: u32,
+    bracket_depth: usize,
+    fields: Vec<FStringField>
+}
+
+// Replacement field open inside an f-string, with bracket depth just after its '{'.
+struct FStringField {
+    paren_depth: u32,
+    in_format_spec: bool
 }
 
 // Declaration of trait for Tokenizer //////////////////////////////////////////////////////////////
 
 pub trait Tokenizer {
-    fn new(buffer: String, tab_size: u8) -> PythonCoreTokenizer;
-    fn tokenize(&self) -> Result<Box<Vec<Box<TokenSymbol>>>, String>;
+    fn new(buffer: String, tab_size: u8) -> Self;
+    fn tokenize(&mut self) -> Result<Box<Vec<Box<TokenSymbol>>>, TokenizerError>;
     fn is_keyword(&self, text: &str, start: u32, end: u32) -> Option<TokenSymbol>;
+    fn is_soft_keyword(&self, text: &str, start: u32, end: u32) -> Option<TokenSymbol>;
     fn is_operator_or_delimiter(&self, c1: char, c2: char, c3: char, start_pos: u32) -> Option<(TokenSymbol, u8)>;
+    fn position(&self, offset: u32) -> Position;
+    fn span(&self, symbol: &TokenSymbol) -> Span;
+}
+
+
+// Matching of keywords, operators and delimiters shared by all source buffers ///////////////////
+
+// Matches reserved keywords and returns token with start and end position.
+pub(crate) fn match_keyword<T>(text: &str, start_pos: u32, end_pos: u32) -> Option<TokenSymbol<T>> {
+    match text {
+        "False"     => Some(TokenSymbol::PyFalse(start_pos, end_pos)),
+        "None"      => Some(TokenSymbol::PyNone(start_pos, end_pos)),
+        "True"      => Some(TokenSymbol::PyTrue(start_pos, end_pos)),
+        "and"       => Some(TokenSymbol::PyAnd(start_pos, end_pos)),
+        "as"        => Some(TokenSymbol::PyAs(start_pos, end_pos)),
+        "assert"    => Some(TokenSymbol::PyAssert(start_pos, end_pos)),
+        "async"     => Some(TokenSymbol::PyAsync(start_pos, end_pos)),
+        "await"     => Some(TokenSymbol::PyAwait(start_pos, end_pos)),
+        "break"     => Some(TokenSymbol::PyBreak(start_pos, end_pos)),
+        "class"     => Some(TokenSymbol::PyClass(start_pos, end_pos)),
+        "continue"  => Some(TokenSymbol::PyContinue(start_pos, end_pos)),
+        "def"       => Some(TokenSymbol::PyDef(start_pos, end_pos)),
+        "del"       => Some(TokenSymbol::PyDel(start_pos, end_pos)),
+        "elif"      => Some(TokenSymbol::PyElif(start_pos, end_pos)),
+        "else"      => Some(TokenSymbol::PyElse(start_pos, end_pos)),
+        "except"    => Some(TokenSymbol::PyExcept(start_pos, end_pos)),
+        "finally"    => Some(TokenSymbol::PyFinally(start_pos, end_pos)),
+        "for"       => Some(TokenSymbol::PyFor(start_pos, end_pos)),
+        "from"      => Some(TokenSymbol::PyFrom(start_pos, end_pos)),
+        "global"    => Some(TokenSymbol::PyGlobal(start_pos, end_pos)),
+        "if"        => Some(TokenSymbol::PyIf(start_pos, end_pos)),
+        "import"    => Some(TokenSymbol::PyImport(start_pos, end_pos)),
+        "in"        => Some(TokenSymbol::PyIn(start_pos, end_pos)),
+        "is"        => Some(TokenSymbol::PyIs(start_pos, end_pos)),
+        "lambda"    => Some(TokenSymbol::PyLambda(start_pos, end_pos)),
+        "nonlocal"  => Some(TokenSymbol::PyNonlocal(start_pos, end_pos)),
+        "not"       => Some(TokenSymbol::PyNot(start_pos, end_pos)),
+        "or"        => Some(TokenSymbol::PyOr(start_pos, end_pos)),
+        "pass"      => Some(TokenSymbol::PyPass(start_pos, end_pos)),
+        "raise"     => Some(TokenSymbol::PyRaise(start_pos, end_pos)),
+        "return"    => Some(TokenSymbol::PyReturn(start_pos, end_pos)),
+        "try"       => Some(TokenSymbol::PyTry(start_pos, end_pos)),
+        "while"     => Some(TokenSymbol::PyWhile(start_pos, end_pos)),
+        "with"      => Some(TokenSymbol::PyWith(start_pos, end_pos)),
+        "yield"     => Some(TokenSymbol::PyYield(start_pos, end_pos)),
+        _ => None
+    }
+}
+
+// Matches soft keywords, which are only keywords in context and is passed on as names otherwise.
+pub(crate) fn match_soft_keyword<T>(text: &str, start_pos: u32, end_pos: u32) -> Option<TokenSymbol<T>> {
+    match text {
+        "match"     => Some(TokenSymbol::PyMatch(start_pos, end_pos)),
+        "case"      => Some(TokenSymbol::PyCase(start_pos, end_pos)),
+        "type"      => Some(TokenSymbol::PyType(start_pos, end_pos)),
+        "_"         => Some(TokenSymbol::PyUnderscore(start_pos, end_pos)),
+        _ => None
+    }
+}
+
+// Matches operators or delimiters and returns tuple with token and steps to go forward in buffer.
+pub(crate) fn match_operator_or_delimiter<T>(c1: char, c2: char, c3: char, start_pos: u32) -> Option<(TokenSymbol<T>, u8)> {
+    match ( c1, c2, c3 ) {
+        ( '*', '*', '=' )   => Some( (TokenSymbol::PyPowerAssign(start_pos, start_pos + 3), 3) ),
+        ( '*', '*', _ )     => Some( (TokenSymbol::PyPower(start_pos, start_pos + 2), 2) ),
+        ( '*', '=', _ )     => Some( (TokenSymbol::PyMulAssign(start_pos, start_pos + 2), 2) ),
+        ( '*', _ , _ )      => Some( (TokenSymbol::PyMul(start_pos, start_pos + 1), 1) ),
+        ( '/', '/', '=' )   => Some( (TokenSymbol::PyFloorDivAssign(start_pos, start_pos + 3), 3) ),
+        ( '/', '/', _ )     => Some( (TokenSymbol::PyFloorDiv(start_pos, start_pos + 2), 2) ),
+        ( '/', '=', _ )     => Some( (TokenSymbol::PyDivAssign(start_pos, start_pos + 2), 2) ),
+        ( '/', _ , _ )      => Some( (TokenSymbol::PyDiv(start_pos, start_pos + 1), 1) ),
+        ( '<', '<', '=' )   => Some( (TokenSymbol::PyShiftLeftAssign(start_pos, start_pos + 3), 3) ),
+        ( '<', '<', _ )     => Some( (TokenSymbol::PyShiftLeft(start_pos, start_pos + 2), 2) ),
+        ( '<', '=', _ )     => Some( (TokenSymbol::PyLessEqual(start_pos, start_pos + 2), 2) ),
+        ( '<', _ , _ )      => Some( (TokenSymbol::PyLess(start_pos, start_pos + 1), 1) ),
+        ( '>', '>', '=' )   => Some( (TokenSymbol::PyShiftRightAssign(start_pos, start_pos + 3), 3) ),
+        ( '>', '>', _ )     => Some( (TokenSymbol::PyShiftRight(start_pos, start_pos + 2), 2) ),
+        ( '>', '=', _ )     => Some( (TokenSymbol::PyGreaterEqual(start_pos, start_pos + 2), 2) ),
+        ( '>', _ , _ )      => Some( (TokenSymbol::PyGreater(start_pos, start_pos + 1), 1) ),
+        ( '.', '.', '.' )   => Some( (TokenSymbol::PyEllipsis(start_pos, start_pos + 3), 3) ),
+        ( '.', _ , _ )      => Some( (TokenSymbol::PyDot(start_pos, start_pos + 1), 1) ),
+        ( '+', '=', _ )     => Some( (TokenSymbol::PyPlusAssign(start_pos, start_pos + 2), 2) ),
+        ( '+', _ , _ )      => Some( (TokenSymbol::PyPlus(start_pos, start_pos + 1), 1) ),
+        ( '-', '=', _ )     => Some( (TokenSymbol::PyMinusAssign(start_pos, start_pos + 2), 2) ),
+        ( '-', '>', _ )     => Some( (TokenSymbol::PyArrow(start_pos, start_pos + 2), 2) ),
+        ( '-', _ , _ )      => Some( (TokenSymbol::PyMinus(start_pos, start_pos + 1), 1) ),
+        ( '%', '=', _ )     => Some( (TokenSymbol::PyModuloAssign(start_pos, start_pos + 2), 2) ),
+        ( '%', _ , _ )      => Some( (TokenSymbol::PyModulo(start_pos, start_pos + 1), 1) ),
+        ( '@', '=', _ )     => Some( (TokenSymbol::PyMatricesAssign(start_pos, start_pos + 2), 2) ),
+        ( '@', _ , _ )      => Some( (TokenSymbol::PyMatrices(start_pos, start_pos + 1), 1) ),
+        ( ':', '=', _ )     => Some( (TokenSymbol::PyColonAssign(start_pos, start_pos + 2), 2) ),
+        ( ':', _ , _ )      => Some( (TokenSymbol::PyColon(start_pos, start_pos + 1), 1) ),
+        ( '&', '=', _ )     => Some( (TokenSymbol::PyBitAndAssign(start_pos, start_pos + 2), 2) ),
+        ( '&', _ , _ )      => Some( (TokenSymbol::PyBitAnd(start_pos, start_pos + 1), 1) ),
+        ( '|', '=', _ )     => Some( (TokenSymbol::PyBitOrAssign(start_pos, start_pos + 2), 2) ),
+        ( '|', _ , _ )      => Some( (TokenSymbol::PyBitOr(start_pos, start_pos + 1), 1) ),
+        ( '^', '=', _ )     => Some( (TokenSymbol::PyBitXorAssign(start_pos, start_pos + 2), 2) ),
+        ( '^', _ , _ )      => Some( (TokenSymbol::PyBitXor(start_pos, start_pos + 1), 1) ),
+        ( '~', _ , _ )      => Some( (TokenSymbol::PyBitInvert(start_pos, start_pos + 1), 1) ),
+        ( ';', _ , _ )      => Some( (TokenSymbol::PySemiColon(start_pos, start_pos + 1), 1) ),
+        ( ',', _ , _ )      => Some( (TokenSymbol::PyComma(start_pos, start_pos + 1), 1) ),
+        ( '=', '=', _ )     => Some( (TokenSymbol::PyEqual(start_pos, start_pos + 2), 2) ),
+        ( '=', _ , _ )      => Some( (TokenSymbol::PyAssign(start_pos, start_pos + 1), 1) ),
+        ( '!', '=', _ )     => Some( (TokenSymbol::PyNotEqual(start_pos, start_pos + 2), 2) ),
+        ( '!', _ , _ )      => Some( (TokenSymbol::PyExclamation(start_pos, start_pos + 1), 1) ),
+        ( '(', _ , _ )      => Some( (TokenSymbol::PyLeftParen(start_pos, start_pos + 1), 1) ),
+        ( ')', _ , _ )      => Some( (TokenSymbol::PyRightParen(start_pos, start_pos + 1), 1) ),
+        ( '[', _ , _ )      => Some( (TokenSymbol::PyLeftBracket(start_pos, start_pos + 1), 1) ),
+        ( ']', _ , _ )      => Some( (TokenSymbol::PyRightBracket(start_pos, start_pos + 1), 1) ),
+        ( '{', _ , _ )      => Some( (TokenSymbol::PyLeftCurly(start_pos, start_pos + 1), 1) ),
+        ( '}', _ , _ )      => Some( (TokenSymbol::PyRightCurly(start_pos, start_pos + 1), 1) ),
+        _ => None
+    }
 }
 
 
 // Start of implementation of trait Tokenizer //////////////////////////////////////////////////////
 
 impl Tokenizer for PythonCoreTokenizer {
-    fn new(buffer: String, tab_size: u8) -> PythonCoreTokenizer {
+    fn new(buffer: String, tab_size: u8) -> Self {
+        PythonCoreTokenizer::with_source(buffer.chars().collect(), tab_size)
+    }
+    // Scans the whole source buffer and returns all tokens, always terminated with PyEof.
+    fn tokenize(&mut self) -> Result<Box<Vec<Box<TokenSymbol>>>, TokenizerError> {
+        self.reset();
+        let mut tokens = Box::new(Vec::new());
+        loop {
+            let symbol = self.next_token_or_recover()?;
+            let is_eof = matches!(symbol, TokenSymbol::PyEof(_, _));
+            tokens.push(Box::new(symbol));
+            if is_eof {
+                break
+            }
+        }
+        Ok(tokens)
+    }
+
+    // Matches reserved keywords and returns token with start and end position.
+    fn is_keyword(&self, text: &str, start_pos: u32, end_pos: u32) -> Option<TokenSymbol> {
+        match_keyword(text, start_pos, end_pos).filter(|symbol| self.is_keyword_in_version(symbol))
+    }
+
+    // Matches soft keywords, which are only keywords in context and is passed on as names otherwise.
+    fn is_soft_keyword(&self, text: &str, start_pos: u32, end_pos: u32) -> Option<TokenSymbol> {
+        match_soft_keyword(text, start_pos, end_pos).filter(|symbol| self.is_keyword_in_version(symbol))
+    }
+
+    // Matches operators or delimiters and returns tuple with token and steps to go forward in buffer.
+    fn is_operator_or_delimiter(&self, c1: char, c2: char, c3: char, start_pos: u32) -> Option<(TokenSymbol, u8)> {
+        match_operator_or_delimiter(c1, c2, c3, start_pos)
+    }
+
+    // Converts character offset in source buffer into line and columns.
+    fn position(&self, offset: u32) -> Position {
+        self.line_index.position(&self.source_buffer, offset)
+    }
+
+    // Returns line and columns for start and end of token.
+    fn span(&self, symbol: &TokenSymbol) -> Span {
+        let ( start, end ) = symbol.offsets();
+        self.line_index.span(&self.source_buffer, start, end)
+    }
+}
+
+
+// Streaming of tokens on demand //////////////////////////////////////////////////////////////////
+
+// Tokens scanned one at a time as they are pulled, ending after PyEof or the first error. Only the
+// dedents of a single line are queued ahead, so memory does not grow with the number of tokens.
+pub struct TokenStream<S: SourceBuffer = Vec<char>> {
+    tokenizer: PythonCoreTokenizer<S>,
+    finished: bool
+}
+
+impl<S: SourceBuffer> TokenStream<S> {
+    // Tokenizer being streamed, for positions and diagnostics of tokens returned so far.
+    pub fn tokenizer(&self) -> &PythonCoreTokenizer<S> {
+        &self.tokenizer
+    }
+}
+
+impl<S: SourceBuffer> Iterator for TokenStream<S> {
+    type Item = Result<TokenSymbol<S::Text>, TokenizerError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None
+        }
+        let result = self.tokenizer.next_token_or_recover();
+        self.finished = matches!(result, Ok(TokenSymbol::PyEof(_, _)) | Err(_));
+        Some(result)
+    }
+}
+
+impl<S: SourceBuffer> FusedIterator for TokenStream<S> {}
+
+impl<S: SourceBuffer> IntoIterator for PythonCoreTokenizer<S> {
+    type Item = Result<TokenSymbol<S::Text>, TokenizerError>;
+    type IntoIter = TokenStream<S>;
+
+    // Streams tokens from start of source buffer.
+    fn into_iter(mut self) -> TokenStream<S> {
+        self.reset();
+        TokenStream { tokenizer: self, finished: false }
+    }
+}
+
+// Construction from undecoded source //////////////////////////////////////////////////////////////
+
+impl PythonCoreTokenizer {
+    // Creates tokenizer for raw source bytes, decoded after any UTF-8 BOM and PEP 263 coding cookie.
+    pub fn from_bytes(buffer: &[u8], tab_size: u8) -> Result<Self, TokenizerError> {
+        match decode_source(buffer) {
+            Ok(text) => Ok(PythonCoreTokenizer::new(text, tab_size)),
+            Err(message) => Err(TokenizerError::InvalidEncoding(0, 0, message.into_boxed_str()))
+        }
+    }
+}
+
+// Borrowing tokenizer over UTF-8 text //////////////////////////////////////////////////////////////
+
+// Tokenizer scanning the text it borrows, with byte offsets in tokens and text of tokens held as
+// slices of the source. Names are not NFKC normalized, as the normalized name may not be in source.
+pub type BorrowedTokenizer<'a> = PythonCoreTokenizer<&'a str>;
+
+impl<'a> PythonCoreTokenizer<&'a str> {
+    pub fn borrowing(source: &'a str, tab_size: u8) -> Self {
+        PythonCoreTokenizer::with_source(source, tab_size)
+    }
+
+    // Converts byte offset in source into line and columns, the same as the owned tokenizer gives.
+    pub fn position(&self, offset: u32) -> Position {
+        self.line_index.position_utf8(self.source_buffer, offset)
+    }
+
+    // Returns line and columns for start and end of token.
+    pub fn span(&self, symbol: &TokenSymbol<&'a str>) -> Span {
+        let ( start, end ) = symbol.offsets();
+        self.line_index.span_utf8(self.source_buffer, start, end)
+    }
+
+    // Scans the whole source and returns all tokens, always terminated with PyEof.
+    pub fn tokenize(&mut self) -> Result<Vec<TokenSymbol<&'a str>>, TokenizerError> {
+        self.reset();
+        let mut tokens = Vec::new();
+        loop {
+            let symbol = self.next_token_or_recover()?;
+            let is_eof = matches!(symbol, TokenSymbol::PyEof(_, _));
+            tokens.push(symbol);
+            if is_eof {
+                break
+            }
+        }
+        Ok(tokens)
+    }
+}
+
+// Options for tokenizer, chained after new() ////////////////////////////////////////////////////
+
+impl<S: SourceBuffer> PythonCoreTokenizer<S> {
+    // Returns '# type: ...' comments as PyTypeComment, as CPython does when type_comments is True.
+    pub fn with_type_comments(mut self, enabled: bool) -> Self {
+        self.type_comments = enabled;
+        self
+    }
+
+    // Returns comments as PyComment instead of skipping them.
+    pub fn with_comments(mut self, enabled: bool) -> Self {
+        self.comments = enabled;
+        self
+    }
+
+    // Returns line breaks not ending a logical line, on blank lines and inside brackets, as PyNl like
+    // the tokenize module of CPython. Implicitly concatenated strings are then not joined across lines.
+    pub fn with_non_logical_newlines(mut self, enabled: bool) -> Self {
+        self.non_logical_newlines = enabled;
+        self
+    }
+
+    // Returns PyError tokens for errors and continues tokenizing, with the errors kept as diagnostics.
+    pub fn with_error_recovery(mut self, enabled: bool) -> Self {
+        self.error_recovery = enabled;
+        self
+    }
+
+    // Tokenizes source as written for a Python version, which decides keywords, soft keywords, string
+    // prefixes, number underscores and whether f-strings are split into PEP 701 tokens. Default is latest.
+    pub fn with_python_version(mut self, version: PythonVersion) -> Self {
+        self.python_version = version;
+        self
+    }
+
+    // Python version source is tokenized for.
+    pub fn python_version(&self) -> PythonVersion {
+        self.python_version
+    }
+
+    // Errors recovered from by the last tokenizing, in order found.
+    pub fn diagnostics(&self) -> &[TokenizerError] {
+        &self.diagnostics
+    }
+}
+
+
+// Lossless tokenizing for formatters and refactoring tools ///////////////////////////////////////
+
+impl PythonCoreTokenizer {
+    // Tokenizes like tokenize(), with source text between tokens attached to the following token as
+    // trivia. Trivia at end of source is attached to PyEof, so no character of the source is lost.
+    pub fn tokenize_lossless(&mut self) -> Result<Vec<LosslessToken>, TokenizerError> {
+        let symbols = self.tokenize()?;
+        let mut tokens = Vec::with_capacity(symbols.len());
+        let mut previous_end: u32 = 0;
+        for symbol in symbols.into_iter() {
+            let ( start, end ) = symbol.offsets();
+            let start = start.max(previous_end);
+            let end = end.max(start);
+            tokens.push(LosslessToken {
+                trivia: self.source_buffer.text(previous_end, start),
+                text: self.source_buffer.text(start, end),
+                symbol: *symbol
+            });
+            previous_end = end
+        }
+        Ok(tokens)
+    }
+}
+
+// Reconstructs the exact source from tokens returned by tokenize_lossless().
+pub fn untokenize(tokens: &[LosslessToken]) -> String {
+    let mut source = String::new();
+    for token in tokens {
+        source.push_str(&token.trivia);
+        source.push_str(&token.text)
+    }
+    source
+}
+
+
+// Tokens with kind, span and text ////////////////////////////////////////////////////////////////
+
+impl PythonCoreTokenizer {
+    // Converts token returned by this tokenizer into a Token, with line and column span of the token.
+    pub fn token(&self, symbol: &TokenSymbol) -> Token {
+        let ( start, end ) = symbol.offsets();
+        Token {
+            kind: symbol.kind(),
+            span: self.line_index.span(&self.source_buffer, start, end),
+            text: match symbol {
+                TokenSymbol::PyName(_, _, name) => name.clone(),
+                _ => self.source_buffer.text(start, end)
+            }
+        }
+    }
+
+    // Converts token returned by this tokenizer into a TokenInfo as the tokenize module of CPython gives.
+    // Tokens ending with a line break ends on the line they start, just after the line break.
+    pub fn token_info(&self, symbol: &TokenSymbol) -> TokenInfo {
+        let ( start, end ) = symbol.offsets();
+        let start_position = self.line_index.position(&self.source_buffer, start);
+        let end_position = match self.source_buffer.char_at(end.max(1) - 1) {
+            '\r' | '\n' if end > start => {
+                let position = self.line_index.position(&self.source_buffer, end - 1);
+                ( position.line, position.column + 1 )
+            },
+            _ => {
+                let position = self.line_index.position(&self.source_buffer, end);
+                ( position.line, position.column )
+            }
+        };
+        let line_start = self.line_index.line_start(start_position.line).unwrap_or(start);
+        let line_end = self.line_index.line_start(end_position.0 + 1).unwrap_or(self.source_buffer.length());
+        TokenInfo {
+            type_name: Box::from(symbol.kind().type_name()),
+            string: self.source_buffer.text(start, end),
+            start: ( start_position.line, start_position.column ),
+            end: end_position,
+            line: self.source_buffer.text(line_start, line_end.max(line_start))
+        }
+    }
+
+    // Converts token into TokenInfo as token_info(), but with one STRING for each literal of implicitly
+    // concatenated strings held by a single PyString, as the tokenize module of CPython gives them.
+    pub fn token_infos(&self, symbol: &TokenSymbol) -> Vec<TokenInfo> {
+        match symbol {
+            TokenSymbol::PyString(start, _, parts) if parts.len() > 1 => {
+                let mut part_start = *start;
+                parts.iter().map(|part| {
+                    let literal = part.trim_start_matches([ ' ', '\t', '\x0c', '\r', '\n', '\\' ]);
+                    let literal_start = part_start + ( part.len() - literal.len() ) as u32;
+                    part_start = literal_start + literal.chars().count() as u32;
+                    self.token_info(&TokenSymbol::PyString(literal_start, part_start, Box::new(vec![ Box::from(literal) ])))
+                }).collect()
+            },
+            _ => vec![ self.token_info(symbol) ]
+        }
+    }
+}
+
+impl<S: SourceBuffer> PythonCoreTokenizer<S> where S::Text: AsRef<str> {
+    // Message of error as CPython words it, with the line where an unterminated literal is detected.
+    pub fn error_message(&self, error: &TokenizerError) -> String {
+        match error {
+            TokenizerError::UnterminatedString(start, detected, _) |
+            TokenizerError::UnterminatedFString(start, detected, _) => {
+                let line = self.line_index.line(detected.saturating_sub(1).max(*start));
+                format!("{} (detected at line {})", error, line)
+            },
+            _ => error.to_string()
+        }
+    }
+
+    // Tokens returned by this tokenizer as Token, which Debug shows with line and column like the tokenize
+    // module of CPython, as in 'OP '**=' 1:4-1:7'. Works for both owned and borrowed tokens.
+    pub fn debug_tokens<B: Borrow<TokenSymbol<S::Text>>>(&self, tokens: &[B]) -> Vec<Token> {
+        tokens.iter().map(|symbol| {
+            let symbol = symbol.borrow();
+            let ( start, end ) = symbol.offsets();
+            Token {
+                kind: symbol.kind(),
+                span: self.source_buffer.span(&self.line_index, start, end),
+                text: match symbol {
+                    TokenSymbol::PyName(_, _, name) => Box::from(name.as_ref()),
+                    _ => Box::from(&*self.source_buffer.str_slice(start, end))
+                }
+            }
+        }).collect()
+    }
+}
+
+
+// Incremental tokenizing after edits of source //////////////////////////////////////////////////
+
+impl PythonCoreTokenizer {
+    // Replaces source between start and end with replacement, and returns tokens of the edited source
+    // given tokens previously returned by tokenize() of this tokenizer. Tokenizing restarts after the
+    // last PyNewline in front of the edit, and tokens after the edit are reused, moved by the change in
+    // length, from the first PyNewline after the edit where brackets, f-strings and indentation are the
+    // same as before. With error recovery enabled, all tokens after the edit are scanned again so
+    // that diagnostics are in order. Source is edited also when an error is returned.
+    pub fn retokenize(&mut self, previous: &[Box<TokenSymbol>], start: u32, end: u32, replacement: &str) -> Result<Box<Vec<Box<TokenSymbol>>>, TokenizerError> {
+        let end = end.min(self.source_buffer.length());
+        let start = start.min(end);
+
+        // Columns of indentation pushed by each PyIndent, measured before source is edited.
+        let indent_columns: Vec<(u32, u32)> = previous.iter().filter_map(|symbol| match **symbol {
+            TokenSymbol::PyIndent(indent_start, indent_end) => Some(self.indentation_columns(indent_start, indent_end)),
+            _ => None
+        }).collect();
+
+        // Empty PyNewline after a bracket never closed depends on the line following it, so it is not restarted from.
+        let restart = previous.iter().rposition(|symbol| matches!(**symbol, TokenSymbol::PyNewline(newline_start, newline_end) if newline_start < newline_end && newline_end < start));
+        let mut tokens = Box::new(Vec::new());
+        let mut diagnostics = std::mem::take(&mut self.diagnostics);
+        self.reset();
+        let mut indents_seen = 0;
+        if let Some(position) = restart {
+            tokens.extend(previous[..= position].iter().cloned());
+            for symbol in tokens.iter() {
+                match **symbol {
+                    TokenSymbol::PyIndent(_, _) => {
+                        let ( column, alt_column ) = indent_columns[indents_seen];
+                        self.indent_stack.push(column);
+                        self.alt_indent_stack.push(alt_column);
+                        indents_seen += 1
+                    },
+                    TokenSymbol::PyDedent(_, _) => {
+                        self.indent_stack.pop();
+                        self.alt_indent_stack.pop();
+                    },
+                    _ => ()
+                }
+            }
+            diagnostics.truncate(tokens.iter().filter(|symbol| matches!(***symbol, TokenSymbol::PyError(_, _, _))).count());
+            self.diagnostics = diagnostics;
+            self.index = tokens[position].offsets().1
+        }
+
+        let replacement: Vec<char> = replacement.chars().collect();
+        let edit_end = start + replacement.len() as u32;
+        let delta = edit_end as i64 - end as i64;
+        self.source_buffer.splice(start as usize .. end as usize, replacement);
+        self.line_index = self.source_buffer.line_index();
+
+        // Indentation before the edit, followed along the previous tokens to compare with when resynchronising.
+        let mut old_indent_stack = self.indent_stack.clone();
+        let mut old_alt_indent_stack = self.alt_indent_stack.clone();
+        let mut old_position = restart.map_or(0, |position| position + 1);
+        loop {
+            let symbol = self.next_token_or_recover()?;
+            let is_eof = matches!(symbol, TokenSymbol::PyEof(_, _));
+            let resync = match symbol {
+                TokenSymbol::PyNewline(newline_start, newline_end) if newline_start >= edit_end && !self.error_recovery => {
+                    let old_start = ( newline_start as i64 - delta ) as u32;
+                    while old_position < previous.len() && previous[old_position].offsets().0 < old_start {
+                        match *previous[old_position] {
+                            TokenSymbol::PyIndent(_, _) => {
+                                let ( column, alt_column ) = indent_columns[indents_seen];
+                                old_indent_stack.push(column);
+                                old_alt_indent_stack.push(alt_column);
+                                indents_seen += 1
+                            },
+                            TokenSymbol::PyDedent(_, _) => {
+                                old_indent_stack.pop();
+                                old_alt_indent_stack.pop();
+                            },
+                            _ => ()
+                        }
+                        old_position += 1
+                    }
+                    matches!(previous.get(old_position).map(|symbol| &**symbol), Some(TokenSymbol::PyNewline(old_newline_start, old_newline_end))
+                        if *old_newline_start == old_start && *old_newline_end as i64 + delta == newline_end as i64)
+                        && old_indent_stack == self.indent_stack && old_alt_indent_stack == self.alt_indent_stack
+                },
+                _ => false
+            };
+            tokens.push(Box::new(symbol));
+            if resync {
+                for symbol in previous[old_position + 1 ..].iter() {
+                    let mut symbol = symbol.clone();
+                    let ( symbol_start, symbol_end ) = symbol.offsets_mut();
+                    *symbol_start = ( *symbol_start as i64 + delta ) as u32;
+                    *symbol_end = ( *symbol_end as i64 + delta ) as u32;
+                    tokens.push(symbol)
+                }
+                break
+            }
+            if is_eof {
+                break
+            }
+        }
+        Ok(tokens)
+    }
+}
+
+
+// Interactive tokenizing for prompt loops ////////////////////////////////////////////////////////
+
+// Status of source typed at an interactive prompt. Source is incomplete with brackets left open, in
+// an unterminated triple-quoted string or after a line continuation, and also when a compound
+// statement is open, also one on a single line such as 'if x: pass', or the last line is a decorator,
+// not yet ended by an empty line, as in the interactive mode of CPython.
+pub enum InteractiveInput {
+    Complete(Box<Vec<Box<TokenSymbol>>>),
+    Incomplete
+}
+
+impl PythonCoreTokenizer {
+    // Tokenizes source typed at prompt so far, and returns Incomplete instead of the error or tokens
+    // when more input is needed, so that a prompt loop can show '...' and ask for the next line.
+    pub fn tokenize_interactive(&mut self) -> Result<InteractiveInput, TokenizerError> {
+        let tokens = match self.tokenize() {
+            Err(error) if is_incomplete_input(&error) => return Ok(InteractiveInput::Incomplete),
+            Err(error) => return Err(error),
+            Ok(tokens) => tokens
+        };
+        if self.diagnostics.iter().any(is_incomplete_input) {
+            return Ok(InteractiveInput::Incomplete)
+        }
+
+        let last_newline = tokens.iter().rposition(|symbol| matches!(**symbol, TokenSymbol::PyNewline(_, _)));
+        let block_open = match last_newline {
+            Some(position) => {
+                let in_block = tokens[position ..].iter().any(|symbol| matches!(**symbol, TokenSymbol::PyDedent(_, _)));
+                let after_colon = matches!(tokens[.. position].iter().rev()
+                    .find(|symbol| !matches!(***symbol, TokenSymbol::PyComment(_, _, _) | TokenSymbol::PyTypeComment(_, _, _) | TokenSymbol::PyNl(_, _)))
+                    .map(|symbol| &**symbol), Some(TokenSymbol::PyColon(_, _)));
+                let line_start = tokens[.. position].iter().rposition(|symbol| matches!(**symbol, TokenSymbol::PyNewline(_, _))).map_or(0, |newline| newline + 1);
+                let is_compound = matches!(tokens[line_start .. position].iter()
+                    .find(|symbol| !matches!(***symbol, TokenSymbol::PyIndent(_, _) | TokenSymbol::PyDedent(_, _) | TokenSymbol::PyComment(_, _, _) | TokenSymbol::PyTypeComment(_, _, _) | TokenSymbol::PyNl(_, _)))
+                    .map(|symbol| &**symbol), Some(
+                        TokenSymbol::PyMatrices(_, _) | TokenSymbol::PyIf(_, _) | TokenSymbol::PyWhile(_, _) | TokenSymbol::PyFor(_, _) |
+                        TokenSymbol::PyTry(_, _) | TokenSymbol::PyWith(_, _) | TokenSymbol::PyDef(_, _) | TokenSymbol::PyClass(_, _) |
+                        TokenSymbol::PyAsync(_, _) | TokenSymbol::PyElif(_, _) | TokenSymbol::PyElse(_, _) | TokenSymbol::PyExcept(_, _) |
+                        TokenSymbol::PyFinally(_, _)
+                    ));
+                in_block || after_colon || is_compound
+            },
+            _ => false
+        };
+        if block_open && !self.ends_with_empty_line() {
+            return Ok(InteractiveInput::Incomplete)
+        }
+        Ok(InteractiveInput::Complete(tokens))
+    }
+
+    // Returns true when source ends with a line break followed by a line of only whitespace.
+    fn ends_with_empty_line(&self) -> bool {
+        let mut offset = self.source_buffer.length();
+        let skip_whitespace = |mut offset: u32| {
+            while offset > 0 && matches!(self.source_buffer.char_at(offset - 1), ' ' | '\t' | '\x0c') {
+                offset -= 1
+            }
+            offset
+        };
+        offset = skip_whitespace(offset);
+        if offset == 0 || !matches!(self.source_buffer.char_at(offset - 1), '\r' | '\n') {
+            return false
+        }
+        if self.source_buffer.char_at(offset - 1) == '\n' && offset > 1 && self.source_buffer.char_at(offset - 2) == '\r' {
+            offset -= 1
+        }
+        offset = skip_whitespace(offset - 1);
+        offset > 0 && matches!(self.source_buffer.char_at(offset - 1), '\r' | '\n')
+    }
+}
+
+// Errors found at end of source that more input typed at an interactive prompt can resolve.
+fn is_incomplete_input(error: &TokenizerError) -> bool {
+    matches!(error, TokenizerError::UnexpectedEof(_, _) | TokenizerError::UnterminatedString(_, _, true) | TokenizerError::UnterminatedFString(_, _, true))
+}
+
+
+// Decoding of string and bytes literals ///////////////////////////////////////////////////////////
+
+impl<S: SourceBuffer> PythonCoreTokenizer<S> where S::Text: AsRef<str> {
+    // Decodes escape sequences of a PyString token returned by this tokenizer, with its implicitly
+    // concatenated literals joined into one string or bytes value. Returns warnings CPython gives for
+    // invalid escape sequences along with the value, and None for tokens other than PyString. F-strings
+    // held by PyString before Python 3.12 have no value without evaluating their fields, and also gives None.
+    pub fn decode_string(&self, symbol: &TokenSymbol<S::Text>) -> Option<Result<(LiteralValue, Vec<LiteralWarning>), TokenizerError>> {
+        let ( start, end, parts ) = match symbol {
+            TokenSymbol::PyString(start, end, parts) => ( *start, *end, parts ),
+            _ => return None
+        };
+        let is_fstring = |text: &str| text[.. text.find([ '\'', '"' ]).unwrap_or(0)].contains([ 'f', 'F' ]);
+        if parts.iter().any(|part| is_fstring(part.as_ref())) {
+            return None
+        }
+        let mut warnings = Vec::new();
+        let mut value: Option<LiteralValue> = None;
+        let mut offset = start;
+        for part in parts.iter() {
+            let text = part.as_ref();
+            let decoded = match decode_literal(text, offset, S::width, &mut warnings) {
+                Ok(decoded) => decoded,
+                Err(error) => return Some(Err(error))
+            };
+            value = match ( value, decoded ) {
+                ( None, decoded ) => Some(decoded),
+                ( Some(LiteralValue::Str(mut joined)), LiteralValue::Str(text) ) => {
+                    joined.push_str(&text);
+                    Some(LiteralValue::Str(joined))
+                },
+                ( Some(LiteralValue::Bytes(mut joined)), LiteralValue::Bytes(bytes) ) => {
+                    joined.extend(bytes);
+                    Some(LiteralValue::Bytes(joined))
+                },
+                _ => return Some(Err(TokenizerError::MixedBytesLiteral(start, end)))
+            };
+            offset += text.chars().map(S::width).sum::<u32>()
+        }
+        value.map(|value| Ok(( value, warnings )))
+    }
+}
+
+
+// Private scanning helpers ////////////////////////////////////////////////////////////////////////
+
+impl<S: SourceBuffer> PythonCoreTokenizer<S> {
+    // Creates tokenizer in its initial state for source buffer.
+    fn with_source(source_buffer: S, tab_size: u8) -> Self {
         PythonCoreTokenizer {
-            source_buffer: Box::new( buffer.chars().collect() ),
+            line_index: source_buffer.line_index(),
+            source_buffer,
             index: 0,
-            tab_size
+            tab_size,
+            type_comments: false,
+            comments: false,
+            non_logical_newlines: false,
+            error_recovery: false,
+            python_version: PythonVersion::LATEST,
+            brackets: Vec::new(),
+            line_has_tokens: false,
+            at_line_start: true,
+            indent_stack: vec![ 0 ],
+            alt_indent_stack: vec![ 0 ],
+            pending: VecDeque::new(),
+            fstring_stack: Vec::new(),
+            token_start: 0,
+            diagnostics: Vec::new()
+        }
+    }
+
+    // Returns character at current index plus offset, or '\0' when outside of buffer.
+    fn peek(&self, offset: u32) -> char {
+        self.source_buffer.char_at(self.index + offset)
+    }
+
+    fn is_at_end(&self) -> bool {
+        self.index >= self.source_buffer.length()
+    }
+
+    fn text(&self, start: u32, end: u32) -> S::Text {
+        self.source_buffer.text(start, end)
+    }
+
+    // Offset just after character at offset.
+    fn char_end(&self, offset: u32) -> u32 {
+        offset + S::width(self.source_buffer.char_at(offset))
+    }
+
+    // Restarts tokenizing from start of source buffer.
+    fn reset(&mut self) {
+        self.index = 0;
+        self.brackets.clear();
+        self.line_has_tokens = false;
+        self.at_line_start = true;
+        self.indent_stack = vec![ 0 ];
+        self.alt_indent_stack = vec![ 0 ];
+        self.pending.clear();
+        self.fstring_stack.clear();
+        self.diagnostics.clear()
+    }
+
+    // Returns next token, or with error recovery enabled, a PyError token in place of an error.
+    fn next_token_or_recover(&mut self) -> Result<TokenSymbol<S::Text>, TokenizerError> {
+        match self.next_token() {
+            Err(error) if self.error_recovery => {
+                let symbol = self.recover(&error);
+                self.diagnostics.push(error);
+                Ok(symbol)
+            },
+            result => result
+        }
+    }
+
+    // Skips source in error up to a point where tokenizing can resume, which is the end of the line
+    // for strings and f-strings, and the end of the bad character, bracket or number otherwise.
+    // Errors in indentation gives an empty PyError token and the line is tokenized as indented.
+    // A bracket never closed gives an empty PyError token and PyNewline, and open brackets are dropped.
+    fn recover(&mut self, error: &TokenizerError) -> TokenSymbol<S::Text> {
+        let ( _, end ) = error.offsets();
+        let start = self.token_start;
+        match error {
+            TokenizerError::UnindentMismatch(_, _) |
+            TokenizerError::InconsistentTabs(_, _) |
+            TokenizerError::TooDeepIndentation(_, _) => {
+                return TokenSymbol::PyError(self.index, self.index, self.text(self.index, self.index))
+            },
+            _ if !self.fstring_stack.is_empty() => {
+                while !self.is_at_end() && !matches!(self.peek(0), '\r' | '\n') {
+                    self.index += 1
+                }
+                self.brackets.truncate(self.fstring_stack[0].bracket_depth);
+                self.fstring_stack.clear()
+            },
+            TokenizerError::UnexpectedEof(_, _) => {
+                self.index = self.source_buffer.length();
+                self.brackets.clear()
+            },
+            TokenizerError::UnclosedBracket(_, _, _) => {
+                self.brackets.clear();
+                self.at_line_start = true;
+                self.line_has_tokens = false;
+                self.pending.push_back(TokenSymbol::PyNewline(self.index, self.index));
+                return TokenSymbol::PyError(self.index, self.index, self.text(self.index, self.index))
+            },
+            TokenizerError::InvalidNumber(_, _, _) |
+            TokenizerError::InvalidDigit(_, _, _, _) |
+            TokenizerError::LeadingZeros(_, _) => {
+                self.index = self.index.max(end);
+                while self.peek(0).is_alphanumeric() || self.peek(0) == '_' {
+                    self.index += S::width(self.peek(0))
+                }
+            },
+            _ => self.index = self.index.max(end)
+        }
+        self.index = self.index.min(self.source_buffer.length());
+        self.line_has_tokens = true;
+        TokenSymbol::PyError(start, self.index, self.text(start, self.index))
+    }
+
+    // Scans and returns next token from current index in buffer.
+    fn next_token(&mut self) -> Result<TokenSymbol<S::Text>, TokenizerError> {
+        loop {
+            if let Some(symbol) = self.pending.pop_front() {
+                return Ok(symbol)
+            }
+
+            if self.is_in_fstring_literal() {
+                self.token_start = self.index;
+                return self.scan_fstring_middle()
+            }
+
+            if self.at_line_start && self.brackets.is_empty() {
+                if let Some(symbol) = self.scan_indentation()? {
+                    return Ok(symbol)
+                }
+                continue
+            }
+
+            while matches!(self.peek(0), ' ' | '\t' | '\x0c') {
+                self.index += 1
+            }
+            self.token_start = self.index;
+
+            if self.is_at_end() {
+                if let Some(mode) = self.fstring_stack.last() {
+                    return Err(self.unterminated_fstring_error(mode))
+                }
+                if let Some(( _, position )) = self.brackets.first() {
+                    return Err(TokenizerError::UnexpectedEof(*position, self.index))
+                }
+                if self.line_has_tokens {
+                    self.line_has_tokens = false;
+                    return Ok(TokenSymbol::PyNewline(self.index, self.index))
+                }
+                if self.indent_stack.len() > 1 {
+                    self.indent_stack.pop();
+                    self.alt_indent_stack.pop();
+                    return Ok(TokenSymbol::PyDedent(self.index, self.index))
+                }
+                return Ok(TokenSymbol::PyEof(self.index, self.index))
+            }
+
+            let start = self.index;
+            match self.peek(0) {
+                '#' => {
+                    while !self.is_at_end() && !matches!(self.peek(0), '\r' | '\n') {
+                        self.index += 1
+                    }
+                    if let Some(symbol) = self.comment_token(start) {
+                        return Ok(symbol)
+                    }
+                },
+                '\r' | '\n' => {
+                    self.index += if self.peek(0) == '\r' && self.peek(1) == '\n' { 2 } else { 1 };
+                    if self.is_statement_after_unclosed_bracket() {
+                        let ( c, position ) = self.brackets[0];
+                        return Err(TokenizerError::UnclosedBracket(position, position + 1, c))
+                    }
+                    if self.brackets.is_empty() {
+                        self.at_line_start = true;
+                        if self.line_has_tokens {
+                            self.line_has_tokens = false;
+                            return Ok(TokenSymbol::PyNewline(start, self.index))
+                        }
+                    }
+                    if self.non_logical_newlines {
+                        return Ok(TokenSymbol::PyNl(start, self.index))
+                    }
+                },
+                '\\' => {
+                    self.scan_line_continuation()?
+                },
+                _ => {
+                    let symbol = self.scan_token()?;
+                    self.line_has_tokens = true;
+                    return Ok(symbol)
+                }
+            }
+        }
+    }
+
+    // Skips backslash and line break joining two physical lines into one logical line.
+    fn scan_line_continuation(&mut self) -> Result<(), TokenizerError> {
+        let start = self.index;
+        match ( self.peek(1), self.peek(2) ) {
+            ( '\r', '\n' ) => self.index += 3,
+            ( '\r' | '\n', _ ) => self.index += 2,
+            _ if self.index + 1 >= self.source_buffer.length() => {
+                return Err(TokenizerError::UnexpectedEof(start, start + 1))
+            },
+            _ => return Err(TokenizerError::UnexpectedCharacterAfterContinuation(start, start + 1))
         }
+        if self.is_at_end() {
+            return Err(TokenizerError::UnexpectedEof(start, self.index))
+        }
+        Ok(())
     }
 
-    fn tokenize(&self) -> Result<Box<Vec<Box<TokenSymbol>>>, String> {
-        let a = Box::new(Vec::from( [ Box::new(TokenSymbol::PyEof) ] ));
-        Ok(a)
+    // Remembers opening bracket and its position until the matching closing bracket.
+    fn open_bracket(&mut self, c: char, start: u32) -> Result<(), TokenizerError> {
+        if self.brackets.len() >= MAX_PAREN_LEVEL {
+            return Err(TokenizerError::TooManyParentheses(start, start + 1))
+        }
+        self.brackets.push(( c, start ));
+        Ok(())
     }
 
-    // Matches reserved keywords and returns token with start and end position.
-    fn is_keyword(&self, text: &str, start_pos: u32, end_pos: u32) -> Option<TokenSymbol> {
-        match text {
-            "False"     => Some(TokenSymbol::PyFalse(start_pos, end_pos)),
-            "None"      => Some(TokenSymbol::PyNone(start_pos, end_pos)),
-            "True"      => Some(TokenSymbol::PyTrue(start_pos, end_pos)),
-            "and"       => Some(TokenSymbol::PyAnd(start_pos, end_pos)),
-            "as"        => Some(TokenSymbol::PyAs(start_pos, end_pos)),
-            "assert"    => Some(TokenSymbol::PyAssert(start_pos, end_pos)),
-            "async"     => Some(TokenSymbol::PyAsync(start_pos, end_pos)),
-            "await"     => Some(TokenSymbol::PyAwait(start_pos, end_pos)),
-            "break"     => Some(TokenSymbol::PyBreak(start_pos, end_pos)),
-            "class"     => Some(TokenSymbol::PyClass(start_pos, end_pos)),
-            "continue"  => Some(TokenSymbol::PyContinue(start_pos, end_pos)),
-            "def"       => Some(TokenSymbol::PyDef(start_pos, end_pos)),
-            "del"       => Some(TokenSymbol::PyDel(start_pos, end_pos)),
-            "elif"      => Some(TokenSymbol::PyElif(start_pos, end_pos)),
-            "else"      => Some(TokenSymbol::PyElse(start_pos, end_pos)),
-            "except"    => Some(TokenSymbol::PyExcept(start_pos, end_pos)),
-            "finally"    => Some(TokenSymbol::PyFinally(start_pos, end_pos)),
-            "for"       => Some(TokenSymbol::PyFor(start_pos, end_pos)),
-            "from"      => Some(TokenSymbol::PyFrom(start_pos, end_pos)),
-            "global"    => Some(TokenSymbol::PyGlobal(start_pos, end_pos)),
-            "if"        => Some(TokenSymbol::PyIf(start_pos, end_pos)),
-            "import"    => Some(TokenSymbol::PyImport(start_pos, end_pos)),
-            "in"        => Some(TokenSymbol::PyIn(start_pos, end_pos)),
-            "is"        => Some(TokenSymbol::PyIs(start_pos, end_pos)),
-            "lambda"    => Some(TokenSymbol::PyLambda(start_pos, end_pos)),
-            "nonlocal"  => Some(TokenSymbol::PyNonlocal(start_pos, end_pos)),
-            "not"       => Some(TokenSymbol::PyNot(start_pos, end_pos)),
-            "or"        => Some(TokenSymbol::PyOr(start_pos, end_pos)),
-            "pass"      => Some(TokenSymbol::PyPass(start_pos, end_pos)),
-            "raise"     => Some(TokenSymbol::PyRaise(start_pos, end_pos)),
-            "return"    => Some(TokenSymbol::PyReturn(start_pos, end_pos)),
-            "try"       => Some(TokenSymbol::PyTry(start_pos, end_pos)),
-            "while"     => Some(TokenSymbol::PyWhile(start_pos, end_pos)),
-            "with"      => Some(TokenSymbol::PyWith(start_pos, end_pos)),
-            "yield"     => Some(TokenSymbol::PyYield(start_pos, end_pos)),
+    // Closing bracket must match the innermost open bracket, worded as in CPython when not.
+    fn close_bracket(&mut self, c: char, start: u32) -> Result<(), TokenizerError> {
+        match self.brackets.pop() {
+            Some(( '(', _ )) if c == ')' => (),
+            Some(( '[', _ )) if c == ']' => (),
+            Some(( '{', _ )) if c == '}' => (),
+            Some(( open, position )) => {
+                let open_line = self.line_index.line(position);
+                let open_line = if open_line == self.line_index.line(start) { None } else { Some(open_line) };
+                return Err(TokenizerError::MismatchedBracket(start, start + 1, c, open, open_line))
+            },
+            _ => return Err(TokenizerError::UnmatchedBracket(start, start + 1, c))
+        }
+        self.close_fstring_field();
+        Ok(())
+    }
+
+    // Returns token for comment just scanned when enabled by options. Type comments are recognized
+    // like in CPython as '#', optional whitespace and 'type:', with text after it kept in the token.
+    fn comment_token(&self, start: u32) -> Option<TokenSymbol<S::Text>> {
+        if self.type_comments {
+            let mut offset = start + 1;
+            while matches!(self.source_buffer.char_at(offset), ' ' | '\t' | '\x0c') {
+                offset += 1
+            }
+            if self.source_buffer.str_slice(offset, self.index).starts_with("type:") {
+                // Text after 'type:' with whitespace trimmed at both ends.
+                let mut text_start = offset + 5;
+                let mut text_end = text_start;
+                while text_start < self.index && self.source_buffer.char_at(text_start).is_whitespace() {
+                    text_start += S::width(self.source_buffer.char_at(text_start))
+                }
+                offset = text_start;
+                while offset < self.index {
+                    let c = self.source_buffer.char_at(offset);
+                    offset += S::width(c);
+                    if !c.is_whitespace() {
+                        text_end = offset
+                    }
+                }
+                return Some(TokenSymbol::PyTypeComment(start, self.index, self.text(text_start, text_end.max(text_start))))
+            }
+        }
+        match self.comments {
+            true => Some(TokenSymbol::PyComment(start, self.index, self.text(start, self.index))),
             _ => None
         }
     }
 
-    // Matches operators or delimiters and returns tuple with token and steps to go forward in buffer.
-    fn is_operator_or_delimiter(&self, c1: char, c2: char, c3: char, start_pos: u32) -> Option<(TokenSymbol, u8)> {
-        match ( c1, c2, c3 ) {
-            ( '*', '*', '=' )   => Some( (TokenSymbol::PyPowerAssign(start_pos, start_pos + 3), 3) ),
-            ( '*', '*', _ )     => Some( (TokenSymbol::PyPower(start_pos, start_pos + 2), 2) ),
-            ( '*', '=', _ )     => Some( (TokenSymbol::PyMulAssign(start_pos, start_pos + 2), 2) ),
-            ( '*', _ , _ )      => Some( (TokenSymbol::PyMul(start_pos, start_pos + 1), 1) ),
-            ( '/', '/', '=' )   => Some( (TokenSymbol::PyFloorDivAssign(start_pos, start_pos + 3), 3) ),
-            ( '/', '/', _ )     => Some( (TokenSymbol::PyFloorDiv(start_pos, start_pos + 2), 2) ),
-            ( '/', '=', _ )     => Some( (TokenSymbol::PyDivAssign(start_pos, start_pos + 2), 2) ),
-            ( '/', _ , _ )      => Some( (TokenSymbol::PyDiv(start_pos, start_pos + 1), 1) ),
-            ( '<', '<', '=' )   => Some( (TokenSymbol::PyShiftLeftAssign(start_pos, start_pos + 3), 3) ),
-            ( '<', '<', _ )     => Some( (TokenSymbol::PyShiftLeft(start_pos, start_pos + 2), 2) ),
-            ( '<', '=', _ )     => Some( (TokenSymbol::PyLessEqual(start_pos, start_pos + 2), 2) ),
-            ( '<', _ , _ )      => Some( (TokenSymbol::PyLess(start_pos, start_pos + 1), 1) ),
-            ( '>', '>', '=' )   => Some( (TokenSymbol::PyShiftRightAssign(start_pos, start_pos + 3), 3) ),
-            ( '>', '>', _ )     => Some( (TokenSymbol::PyShiftRight(start_pos, start_pos + 2), 2) ),
-            ( '>', '=', _ )     => Some( (TokenSymbol::PyGreaterEqual(start_pos, start_pos + 2), 2) ),
-            ( '>', _ , _ )      => Some( (TokenSymbol::PyGreater(start_pos, start_pos + 1), 1) ),
-            ( '.', '.', '.' )   => Some( (TokenSymbol::PyEllipsis(start_pos, start_pos + 3), 3) ),
-            ( '.', _ , _ )      => Some( (TokenSymbol::PyDot(start_pos, start_pos + 1), 1) ),
-            ( '+', '=', _ )     => Some( (TokenSymbol::PyPlusAssign(start_pos, start_pos + 2), 2) ),
-            ( '+', _ , _ )      => Some( (TokenSymbol::PyPlus(start_pos, start_pos + 1), 1) ),
-            ( '-', '=', _ )     => Some( (TokenSymbol::PyMinusAssign(start_pos, start_pos + 2), 2) ),
-            ( '-', '>', _ )     => Some( (TokenSymbol::PyArrow(start_pos, start_pos + 2), 2) ),
-            ( '-', _ , _ )      => Some( (TokenSymbol::PyMinus(start_pos, start_pos + 1), 1) ),
-            ( '%', '=', _ )     => Some( (TokenSymbol::PyModuloAssign(start_pos, start_pos + 2), 2) ),
-            ( '%', _ , _ )      => Some( (TokenSymbol::PyModulo(start_pos, start_pos + 1), 1) ),
-            ( '@', '=', _ )     => Some( (TokenSymbol::PyMatricesAssign(start_pos, start_pos + 2), 2) ),
-            ( '@', _ , _ )      => Some( (TokenSymbol::PyMatrices(start_pos, start_pos + 1), 1) ),
-            ( ':', '=', _ )     => Some( (TokenSymbol::PyColonAssign(start_pos, start_pos + 2), 2) ),
-            ( ':', _ , _ )      => Some( (TokenSymbol::PyColon(start_pos, start_pos + 1), 1) ),
-            ( '&', '=', _ )     => Some( (TokenSymbol::PyBitAndAssign(start_pos, start_pos + 2), 2) ),
-            ( '&', _ , _ )      => Some( (TokenSymbol::PyBitAnd(start_pos, start_pos + 1), 1) ),
-            ( '|', '=', _ )     => Some( (TokenSymbol::PyBitOrAssign(start_pos, start_pos + 2), 2) ),
-            ( '|', _ , _ )      => Some( (TokenSymbol::PyBitOr(start_pos, start_pos + 1), 1) ),
-            ( '^', '=', _ )     => Some( (TokenSymbol::PyBitXorAssign(start_pos, start_pos + 2), 2) ),
-            ( '^', _ , _ )      => Some( (TokenSymbol::PyBitXor(start_pos, start_pos + 1), 1) ),
-            ( '~', _ , _ )      => Some( (TokenSymbol::PyBitInvert(start_pos, start_pos + 1), 1) ),
-            ( ';', _ , _ )      => Some( (TokenSymbol::PySemiColon(start_pos, start_pos + 1), 1) ),
-            ( ',', _ , _ )      => Some( (TokenSymbol::PyComma(start_pos, start_pos + 1), 1) ),
-            ( '=', '=', _ )     => Some( (TokenSymbol::PyEqual(start_pos, start_pos + 2), 2) ),
-            ( '=', _ , _ )      => Some( (TokenSymbol::PyAssign(start_pos, start_pos + 1), 1) ),
-            ( '!', '=', _ )     => Some( (TokenSymbol::PyNotEqual(start_pos, start_pos + 2), 2) ),
-            ( '(', _ , _ )      => Some( (TokenSymbol::PyLeftParen(start_pos, start_pos + 1), 1) ),
-            ( ')', _ , _ )      => Some( (TokenSymbol::PyRightParen(start_pos, start_pos + 1), 1) ),
-            ( '[', _ , _ )      => Some( (TokenSymbol::PyLeftBracket(start_pos, start_pos + 1), 1) ),
-            ( ']', _ , _ )      => Some( (TokenSymbol::PyRightBracket(start_pos, start_pos + 1), 1) ),
-            ( '{', _ , _ )      => Some( (TokenSymbol::PyLeftCurly(start_pos, start_pos + 1), 1) ),
-            ( '}', _ , _ )      => Some( (TokenSymbol::PyRightCurly(start_pos, start_pos + 1), 1) ),
+    // Measures indentation at start of a logical line and returns PyIndent, or queues PyDedent tokens.
+    // Tabs advance column to next multiple of tab_size and form feed resets column, as in CPython.
+    // Column is also measured with a tab size of one and both must agree, else indentation depends
+    // on the tab width of the editor and is reported as inconsistent use of tabs and spaces.
+    fn scan_indentation(&mut self) -> Result<Option<TokenSymbol<S::Text>>, TokenizerError> {
+        let line_start = self.index;
+        while matches!(self.peek(0), ' ' | '\t' | '\x0c') {
+            self.index += 1
+        }
+        let ( column, alt_column ) = self.indentation_columns(line_start, self.index);
+
+        // Blank and comment only lines does not affect indentation.
+        if self.is_at_end() || matches!(self.peek(0), '#' | '\r' | '\n') {
+            self.at_line_start = false;
+            return Ok(None)
+        }
+        self.at_line_start = false;
+
+        let top = *self.indent_stack.last().unwrap_or(&0);
+        let alt_top = *self.alt_indent_stack.last().unwrap_or(&0);
+        if column == top {
+            if alt_column != alt_top {
+                return Err(self.tab_error())
+            }
+            return Ok(None)
+        }
+        if column > top {
+            if alt_column <= alt_top {
+                return Err(self.tab_error())
+            }
+            if self.indent_stack.len() >= MAX_INDENT_LEVEL {
+                return Err(TokenizerError::TooDeepIndentation(self.index, self.index))
+            }
+            self.indent_stack.push(column);
+            self.alt_indent_stack.push(alt_column);
+            return Ok(Some(TokenSymbol::PyIndent(line_start, self.index)))
+        }
+        while column < *self.indent_stack.last().unwrap_or(&0) {
+            self.indent_stack.pop();
+            self.alt_indent_stack.pop();
+            self.pending.push_back(TokenSymbol::PyDedent(self.index, self.index))
+        }
+        if column != *self.indent_stack.last().unwrap_or(&0) {
+            return Err(TokenizerError::UnindentMismatch(self.index, self.index))
+        }
+        if alt_column != *self.alt_indent_stack.last().unwrap_or(&0) {
+            return Err(self.tab_error())
+        }
+        Ok(None)
+    }
+
+    // Columns after whitespace between offsets, with tab size of tokenizer and with tab size of one.
+    fn indentation_columns(&self, start: u32, end: u32) -> (u32, u32) {
+        let tab_size = self.tab_size.max(1) as u32;
+        let mut column: u32 = 0;
+        let mut alt_column: u32 = 0;
+        for offset in start .. end {
+            match self.source_buffer.char_at(offset) {
+                '\t' => {
+                    column = ( column / tab_size + 1 ) * tab_size;
+                    alt_column += 1
+                },
+                '\x0c' => {
+                    column = 0;
+                    alt_column = 0
+                },
+                _ => {
+                    column += 1;
+                    alt_column += 1
+                }
+            }
+        }
+        ( column, alt_column )
+    }
+
+    // With error recovery, a line inside brackets starting with a keyword only found at start of statements,
+    // at or below indentation of the line where the outermost open bracket is, ends that bracket as never
+    // closed. Keywords also found inside expressions, such as 'if', 'for' and 'else', are not taken.
+    fn is_statement_after_unclosed_bracket(&self) -> bool {
+        let position = match self.brackets.first() {
+            Some(( _, position )) if self.error_recovery && self.fstring_stack.is_empty() => *position,
+            _ => return false
+        };
+        let skip_whitespace = |mut offset: u32| {
+            while matches!(self.source_buffer.char_at(offset), ' ' | '\t' | '\x0c') {
+                offset += 1
+            }
+            offset
+        };
+        let bracket_line_start = self.line_index.line_start(self.line_index.line(position)).unwrap_or(0);
+        let bracket_indent = self.indentation_columns(bracket_line_start, skip_whitespace(bracket_line_start));
+        let start = skip_whitespace(self.index);
+        if self.indentation_columns(self.index, start).0 > bracket_indent.0 {
+            return false
+        }
+        let mut end = start;
+        while self.source_buffer.char_at(end).is_ascii_alphabetic() {
+            end += 1
+        }
+        let c = self.source_buffer.char_at(end);
+        if c.is_ascii_digit() || c == '_' || !c.is_ascii() {
+            return false
+        }
+        matches!(&*self.source_buffer.str_slice(start, end),
+            "assert" | "break" | "class" | "continue" | "def" | "del" | "elif" | "except" | "finally" |
+            "global" | "import" | "nonlocal" | "pass" | "raise" | "return" | "try" | "while" | "with")
+    }
+
+    fn tab_error(&self) -> TokenizerError {
+        TokenizerError::InconsistentTabs(self.index, self.index)
+    }
+
+    // Scans a single name, keyword, number, string, operator or delimiter.
+    fn scan_token(&mut self) -> Result<TokenSymbol<S::Text>, TokenizerError> {
+        let start = self.index;
+        let c = self.peek(0);
+
+        if let Some(length) = self.string_prefix_length(0) {
+            if self.is_fstring_start() {
+                return Ok(self.scan_fstring_start(length))
+            }
+            return self.scan_string()
+        }
+
+        if c.is_ascii_alphabetic() || c == '_' || !c.is_ascii() {
+            return self.scan_name()
+        }
+
+        if c.is_ascii_digit() || ( c == '.' && self.peek(1).is_ascii_digit() ) {
+            return self.scan_number()
+        }
+
+        // Colon directly inside a replacement field starts the format specifier, also in front of '='.
+        if c == ':' && self.is_at_fstring_field_level() {
+            self.index += 1;
+            if let Some(field) = self.fstring_stack.last_mut().and_then(|mode| mode.fields.last_mut()) {
+                field.in_format_spec = true
+            }
+            return Ok(TokenSymbol::PyColon(start, self.index))
+        }
+
+        match match_operator_or_delimiter(c, self.peek(1), self.peek(2), start) {
+            Some( ( symbol, steps ) ) => {
+                match symbol {
+                    TokenSymbol::PyLeftParen(_, _) | TokenSymbol::PyLeftBracket(_, _) | TokenSymbol::PyLeftCurly(_, _) => {
+                        self.open_bracket(c, start)?
+                    },
+                    TokenSymbol::PyRightParen(_, _) | TokenSymbol::PyRightBracket(_, _) | TokenSymbol::PyRightCurly(_, _) => {
+                        self.close_bracket(c, start)?
+                    },
+                    _ => ()
+                }
+                self.index += steps as u32;
+                Ok(symbol)
+            },
+            _ => Err(TokenizerError::InvalidCharacter(start, start + 1, c))
+        }
+    }
+
+    // Scans identifier or keyword. Any non ASCII character is taken as part of the name, and the name
+    // is checked against XID_Start and XID_Continue from PEP 3131 before being NFKC normalized.
+    fn scan_name(&mut self) -> Result<TokenSymbol<S::Text>, TokenizerError> {
+        let start = self.index;
+        while self.peek(0).is_ascii_alphanumeric() || self.peek(0) == '_' || !self.peek(0).is_ascii() {
+            self.index += 1
+        }
+        let text = self.source_buffer.str_slice(start, self.index);
+        if text.is_ascii() {
+            return match match_keyword(&text, start, self.index).or_else(|| match_soft_keyword(&text, start, self.index)) {
+                Some(symbol) if self.is_keyword_in_version(&symbol) => Ok(symbol),
+                _ => Ok(TokenSymbol::PyName(start, self.index, self.text(start, self.index)))
+            }
+        }
+
+        let mut offset = start;
+        for c in text.chars() {
+            let is_valid = match offset == start {
+                true => c == '_' || is_xid_start(c),
+                _ => is_xid_continue(c)
+            };
+            if !is_valid {
+                return Err(TokenizerError::InvalidCharacter(offset, offset + S::width(c), c))
+            }
+            offset += S::width(c)
+        }
+        Ok(TokenSymbol::PyName(start, self.index, self.source_buffer.name_text(start, self.index)))
+    }
+
+    // Keywords added in later versions of Python are names in source for earlier versions.
+    fn is_keyword_in_version<T>(&self, symbol: &TokenSymbol<T>) -> bool {
+        match symbol {
+            TokenSymbol::PyAsync(_, _) | TokenSymbol::PyAwait(_, _) => self.python_version.has_async_keywords(),
+            TokenSymbol::PyMatch(_, _) | TokenSymbol::PyCase(_, _) | TokenSymbol::PyUnderscore(_, _) => self.python_version.has_match_statement(),
+            TokenSymbol::PyType(_, _) => self.python_version.has_type_statement(),
+            _ => true
+        }
+    }
+
+    // Scans integer, float and imaginary literals in decimal, hexadecimal, octal and binary form, with
+    // PEP 515 underscores between digits. Follows the rules and error messages of CPython tokenizer.
+    fn scan_number(&mut self) -> Result<TokenSymbol<S::Text>, TokenizerError> {
+        let start = self.index;
+        match ( self.peek(0), self.peek(1) ) {
+            ( '0', 'x' | 'X' ) => {
+                self.index += 2;
+                self.scan_radix_digits(|c| c.is_ascii_hexdigit(), "hexadecimal")?;
+                self.verify_end_of_number("hexadecimal")?
+            },
+            ( '0', 'o' | 'O' ) => {
+                self.index += 2;
+                self.scan_radix_digits(|c| ('0'..='7').contains(&c), "octal")?;
+                self.verify_end_of_number("octal")?
+            },
+            ( '0', 'b' | 'B' ) => {
+                self.index += 2;
+                self.scan_radix_digits(|c| c == '0' || c == '1', "binary")?;
+                self.verify_end_of_number("binary")?
+            },
+            _ => {
+                if self.peek(0) == '0' {
+                    // Only zeros are allowed in integer literal starting with zero, other digits are
+                    // accepted when the literal turns out to be a float or imaginary number.
+                    loop {
+                        if self.peek(0) == '_' && self.python_version.has_number_underscores() {
+                            self.index += 1;
+                            if !self.peek(0).is_ascii_digit() {
+                                return Err(TokenizerError::InvalidNumber(self.index, self.char_end(self.index), "decimal"))
+                            }
+                        }
+                        if self.peek(0) != '0' {
+                            break
+                        }
+                        self.index += 1
+                    }
+                    let has_nonzero = self.peek(0).is_ascii_digit();
+                    if has_nonzero {
+                        self.scan_decimal_tail()?
+                    }
+                    if has_nonzero && !matches!(self.peek(0), '.' | 'e' | 'E' | 'j' | 'J') {
+                        return Err(TokenizerError::LeadingZeros(start, self.index))
+                    }
+                }
+                else if self.peek(0) != '.' {
+                    self.scan_decimal_tail()?
+                }
+
+                if self.peek(0) == '.' {
+                    self.index += 1;
+                    if self.peek(0).is_ascii_digit() {
+                        self.scan_decimal_tail()?
+                    }
+                }
+
+                if matches!(self.peek(0), 'e' | 'E') {
+                    match ( self.peek(1), self.peek(2) ) {
+                        ( '+' | '-', c ) if c.is_ascii_digit() => {
+                            self.index += 2;
+                            self.scan_decimal_tail()?
+                        },
+                        ( '+' | '-', _ ) => return Err(TokenizerError::InvalidNumber(self.index + 2, self.char_end(self.index + 2), "decimal")),
+                        ( c, _ ) if c.is_ascii_digit() => {
+                            self.index += 1;
+                            self.scan_decimal_tail()?
+                        },
+                        _ => ()
+                    }
+                }
+
+                if matches!(self.peek(0), 'j' | 'J') {
+                    self.index += 1;
+                    self.verify_end_of_number("imaginary")?
+                }
+                else {
+                    self.verify_end_of_number("decimal")?
+                }
+            }
+        }
+        Ok(TokenSymbol::PyNumber(start, self.index, self.text(start, self.index)))
+    }
+
+    // Scans digits where each single underscore must be followed by a digit.
+    fn scan_decimal_tail(&mut self) -> Result<(), TokenizerError> {
+        loop {
+            while self.peek(0).is_ascii_digit() {
+                self.index += 1
+            }
+            if self.peek(0) != '_' || !self.python_version.has_number_underscores() {
+                return Ok(())
+            }
+            self.index += 1;
+            if !self.peek(0).is_ascii_digit() {
+                return Err(TokenizerError::InvalidNumber(self.index, self.char_end(self.index), "decimal"))
+            }
+        }
+    }
+
+    // Scans digits after a 0x, 0o or 0b prefix, where underscore is allowed before each group of digits.
+    fn scan_radix_digits(&mut self, is_digit: fn(char) -> bool, kind: &'static str) -> Result<(), TokenizerError> {
+        let has_underscores = self.python_version.has_number_underscores();
+        loop {
+            if self.peek(0) == '_' && has_underscores {
+                self.index += 1
+            }
+            if !is_digit(self.peek(0)) {
+                break
+            }
+            while is_digit(self.peek(0)) {
+                self.index += 1
+            }
+            if self.peek(0) != '_' || !has_underscores {
+                if self.peek(0).is_ascii_digit() {
+                    break
+                }
+                return Ok(())
+            }
+        }
+        match self.peek(0) {
+            c if c.is_ascii_digit() => Err(TokenizerError::InvalidDigit(self.index, self.index + 1, c, kind)),
+            _ => Err(TokenizerError::InvalidNumber(self.index, self.char_end(self.index), kind))
+        }
+    }
+
+    // Number literals must not be directly followed by a name, except the keywords that can follow
+    // a number in valid code such as '1if x else 2', which CPython still accepts.
+    fn verify_end_of_number(&self, kind: &'static str) -> Result<(), TokenizerError> {
+        let is_keyword_ahead = |text: &str| text.chars().enumerate().all(|(i, c)| self.peek(i as u32) == c);
+        let c = self.peek(0);
+        let keyword_follows = match c {
+            'a' => is_keyword_ahead("and"),
+            'e' => is_keyword_ahead("else"),
+            'f' => is_keyword_ahead("for"),
+            'i' => matches!(self.peek(1), 'f' | 'n' | 's'),
+            'n' => is_keyword_ahead("not"),
+            'o' => is_keyword_ahead("or"),
+            _ => false
+        };
+        if !keyword_follows && ( c.is_ascii_alphanumeric() || c == '_' ) {
+            return Err(TokenizerError::InvalidNumber(self.index, self.index + 1, kind))
+        }
+        Ok(())
+    }
+
+    // Returns length of prefix when a string literal starts at index plus offset. Legal prefixes are
+    // 'r', 'u', 'b', 'f', 'br', 'rb', 'fr' and 'rf' in any combination of upper and lower case, as far
+    // as the Python version has them.
+    fn string_prefix_length(&self, offset: u32) -> Option<u32> {
+        let mut length = 0;
+        while length < 3 && self.peek(offset + length).is_ascii_alphabetic() {
+            length += 1
+        }
+        if !matches!(self.peek(offset + length), '"' | '\'') {
+            return None
+        }
+        let first = if length > 0 { self.peek(offset).to_ascii_lowercase() } else { '\0' };
+        let second = if length > 1 { self.peek(offset + 1).to_ascii_lowercase() } else { '\0' };
+        match ( length, first, second ) {
+            ( 0, _, _ ) | ( 1, 'r' | 'b', _ ) | ( 2, 'b', 'r' ) => Some(length),
+            ( 1, 'u', _ ) | ( 2, 'r', 'b' ) if self.python_version.has_unicode_prefix() => Some(length),
+            ( 1, 'f', _ ) | ( 2, 'f', 'r' ) | ( 2, 'r', 'f' ) if self.python_version.has_fstrings() => Some(length),
             _ => None
         }
     }
 
+    // Scans a string literal and all literals directly following it, separated only by whitespace or
+    // by newlines inside brackets, into a single PyString holding the text of each literal. Literals
+    // after the first are held with the whitespace and line continuations in front of them, so that the
+    // parts joined are the exact source text of the token.
+    fn scan_string(&mut self) -> Result<TokenSymbol<S::Text>, TokenizerError> {
+        let start = self.index;
+        let mut parts = Vec::new();
+        let mut part_start = self.index;
+        loop {
+            self.scan_string_literal()?;
+            parts.push(self.text(part_start, self.index));
+
+            let end = self.index;
+            loop {
+                match self.peek(0) {
+                    ' ' | '\t' | '\x0c' => self.index += 1,
+                    '\r' | '\n' if !self.brackets.is_empty() && !self.non_logical_newlines => self.index += 1,
+                    '\\' if matches!(self.peek(1), '\r' | '\n') => self.index += 2,
+                    _ => break
+                }
+            }
+            if self.string_prefix_length(0).is_none() || self.is_fstring_start() {
+                self.index = end;
+                return Ok(TokenSymbol::PyString(start, end, Box::new(parts)))
+            }
+            part_start = end
+        }
+    }
+
+    // Scans one string literal with prefix, in single or triple quotes. Backslash always escapes the
+    // next character, also in raw strings where it is kept in the literal.
+    fn scan_string_literal(&mut self) -> Result<(), TokenizerError> {
+        let start = self.index;
+        self.index += self.string_prefix_length(0).unwrap_or(0);
+        let quote = self.peek(0);
+        let is_triple = self.peek(1) == quote && self.peek(2) == quote;
+        self.index += if is_triple { 3 } else { 1 };
+        loop {
+            match self.peek(0) {
+                _ if self.is_at_end() => break,
+                '\r' | '\n' if !is_triple => break,
+                '\\' if self.peek(1) == '\r' && self.peek(2) == '\n' => self.index += 3,
+                '\\' => self.index += 2,
+                c if c == quote && ( !is_triple || ( self.peek(1) == quote && self.peek(2) == quote ) ) => {
+                    self.index += if is_triple { 3 } else { 1 };
+                    return Ok(())
+                },
+                _ => self.index += 1
+            }
+        }
+        let detected = self.index.min(self.source_buffer.length());
+        Err(TokenizerError::UnterminatedString(start, detected, is_triple))
+    }
+
+
+    // Returns true when an f-string literal, with 'f' or 'F' in its prefix, starts at index and is to be
+    // scanned into PEP 701 tokens. Before Python 3.12 f-strings are scanned as any other string literal.
+    fn is_fstring_start(&self) -> bool {
+        if !self.python_version.has_fstring_tokens() {
+            return false
+        }
+        match self.string_prefix_length(0) {
+            Some(length) => ( 0 .. length ).any(|i| matches!(self.peek(i), 'f' | 'F')),
+            _ => false
+        }
+    }
+
+    // Returns true when next token is part of literal text of an f-string or its format specifier.
+    fn is_in_fstring_literal(&self) -> bool {
+        match self.fstring_stack.last() {
+            Some(mode) => match mode.fields.last() {
+                Some(field) => field.in_format_spec,
+                _ => true
+            },
+            _ => false
+        }
+    }
+
+    // Returns true when directly inside a replacement field, outside of any bracket opened within it.
+    fn is_at_fstring_field_level(&self) -> bool {
+        match self.fstring_stack.last().and_then(|mode| mode.fields.last()) {
+            Some(field) => !field.in_format_spec && field.paren_depth == self.brackets.len() as u32,
+            _ => false
+        }
+    }
+
+    // Closes replacement field when its '}' has been matched as a closing bracket.
+    fn close_fstring_field(&mut self) {
+        let paren_depth = self.brackets.len() as u32;
+        if let Some(mode) = self.fstring_stack.last_mut() {
+            if matches!(mode.fields.last(), Some(field) if paren_depth < field.paren_depth) {
+                mode.fields.pop();
+            }
+        }
+    }
+
+    // Scans prefix and opening quotes of an f-string, and enters f-string mode for the literal text.
+    fn scan_fstring_start(&mut self, prefix_length: u32) -> TokenSymbol<S::Text> {
+        let start = self.index;
+        let is_raw = ( 0 .. prefix_length ).any(|i| matches!(self.peek(i), 'r' | 'R'));
+        self.index += prefix_length;
+        let quote = self.peek(0);
+        let is_triple = self.peek(1) == quote && self.peek(2) == quote;
+        self.index += if is_triple { 3 } else { 1 };
+        let bracket_depth = self.brackets.len();
+        self.fstring_stack.push(FStringMode { quote, is_triple, is_raw, start, bracket_depth, fields: Vec::new() });
+        TokenSymbol::PyFStringStart(start, self.index, self.text(start, self.index))
+    }
+
+    // Scans literal text of an f-string up to next replacement field or closing quotes. Doubled braces
+    // are kept as a single brace and end the text, as in CPython. When no text is found, returns the
+    // '{' opening a field, '}' ending a format specifier or the closing quotes instead.
+    fn scan_fstring_middle(&mut self) -> Result<TokenSymbol<S::Text>, TokenizerError> {
+        let ( quote, is_triple, is_raw, in_format_spec ) = match self.fstring_stack.last() {
+            Some(mode) => ( mode.quote, mode.is_triple, mode.is_raw, !mode.fields.is_empty() ),
+            _ => return Err(TokenizerError::InvalidFString(self.index, self.index, "f-string mode expected"))
+        };
+        let start = self.index;
+        let mut text_end = None;
+        loop {
+            let c = self.peek(0);
+            if self.is_at_end() || ( !is_triple && matches!(c, '\r' | '\n') ) {
+                if in_format_spec && !self.is_at_end() {
+                    return Err(TokenizerError::InvalidFString(self.index, self.index + 1, "f-string: newlines are not allowed in format specifiers for single quoted f-strings"))
+                }
+                return Err(self.unterminated_fstring_error(self.fstring_stack.last().unwrap()))
+            }
+            if c == quote && ( !is_triple || ( self.peek(1) == quote && self.peek(2) == quote ) ) {
+                if in_format_spec {
+                    return Err(TokenizerError::InvalidFString(self.index, self.index + 1, "f-string: expecting '}'"))
+                }
+                if self.index > start {
+                    break
+                }
+                self.index += if is_triple { 3 } else { 1 };
+                self.fstring_stack.pop();
+                return Ok(TokenSymbol::PyFStringEnd(start, self.index, self.text(start, self.index)))
+            }
+            match c {
+                '{' | '}' if !in_format_spec && self.peek(1) == c => {
+                    text_end = Some(self.index + 1);
+                    self.index += 2;
+                    break
+                },
+                '{' => {
+                    if self.index > start {
+                        break
+                    }
+                    self.open_bracket(c, start)?;
+                    self.index += 1;
+                    let paren_depth = self.brackets.len() as u32;
+                    if let Some(mode) = self.fstring_stack.last_mut() {
+                        mode.fields.push(FStringField { paren_depth, in_format_spec: false })
+                    }
+                    return Ok(TokenSymbol::PyLeftCurly(start, self.index))
+                },
+                '}' if in_format_spec => {
+                    if self.index > start {
+                        break
+                    }
+                    self.close_bracket(c, start)?;
+                    self.index += 1;
+                    return Ok(TokenSymbol::PyRightCurly(start, self.index))
+                },
+                '}' => return Err(TokenizerError::InvalidFString(self.index, self.index + 1, "f-string: single '}' is not allowed")),
+                '\\' => {
+                    self.index += 1;
+                    if !is_raw && self.peek(0) == 'N' && self.peek(1) == '{' {
+                        while !self.is_at_end() && !matches!(self.peek(0), '}' | '\r' | '\n') {
+                            self.index += 1
+                        }
+                        if self.peek(0) == '}' {
+                            self.index += 1
+                        }
+                    }
+                    else if !self.is_at_end() && !matches!(self.peek(0), '{' | '}') {
+                        if self.peek(0) == '\r' && self.peek(1) == '\n' {
+                            self.index += 1
+                        }
+                        self.index += S::width(self.peek(0))
+                    }
+                },
+                _ => self.index += S::width(c)
+            }
+        }
+        Ok(TokenSymbol::PyFStringMiddle(start, self.index, self.text(start, text_end.unwrap_or(self.index))))
+    }
+
+    fn unterminated_fstring_error(&self, mode: &FStringMode) -> TokenizerError {
+        let detected = self.index.min(self.source_buffer.length());
+        TokenizerError::UnterminatedFString(mode.start, detected, mode.is_triple)
+    }
 }
 
 
 #[cfg(test)]
+#[allow(clippy::assertions_on_constants)]
 mod tests {
     use super::*;
+    use crate::parser::token::{TokenCategory, TokenKind};
+    use crate::parser::version::PythonVersion;
 
     #[test]
     fn reserved_keyword_false() {
@@ -603,6 +2022,72 @@ mod tests {
         }
     }
 
+    #[test]
+    fn soft_keyword_match() {
+        let lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("Unused!"), 4);
+        let symbol = lexer.is_soft_keyword("match", 1, 6);
+        match symbol {
+            Some(TokenSymbol::PyMatch(1, 6)) => assert!(true),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn soft_keyword_case() {
+        let lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("Unused!"), 4);
+        let symbol = lexer.is_soft_keyword("case", 1, 5);
+        match symbol {
+            Some(TokenSymbol::PyCase(1, 5)) => assert!(true),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn soft_keyword_type() {
+        let lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("Unused!"), 4);
+        let symbol = lexer.is_soft_keyword("type", 1, 5);
+        match symbol {
+            Some(TokenSymbol::PyType(1, 5)) => assert!(true),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn soft_keyword_underscore() {
+        let lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("Unused!"), 4);
+        let symbol = lexer.is_soft_keyword("_", 1, 2);
+        match symbol {
+            Some(TokenSymbol::PyUnderscore(1, 2)) => assert!(true),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn soft_keyword_not_a_soft_keyword() {
+        let lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("Unused!"), 4);
+        let symbol = lexer.is_soft_keyword("if", 1, 3);
+        match symbol {
+            None => assert!(true),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn soft_keyword_as_name() {
+        match TokenSymbol::PyMatch(3, 8).as_name() {
+            Some(TokenSymbol::PyName(3, 8, text)) => assert_eq!(&*text, "match"),
+            _ => assert!(false)
+        }
+        match TokenSymbol::PyUnderscore(0, 1).as_name() {
+            Some(TokenSymbol::PyName(0, 1, text)) => assert_eq!(&*text, "_"),
+            _ => assert!(false)
+        }
+        match TokenSymbol::PyIf(0, 2).as_name() {
+            None => assert!(true),
+            _ => assert!(false)
+        }
+    }
+
     #[test]
     fn operator_or_delimiter_power_assign() {
         let lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("Unused!"), 4);
@@ -983,6 +2468,16 @@ mod tests {
         }
     }
 
+    #[test]
+    fn operator_or_delimiter_exclamation() {
+        let lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("Unused!"), 4);
+        let symbol = lexer.is_operator_or_delimiter('!', 'r', ' ', 1);
+        match symbol {
+            Some( ( TokenSymbol::PyExclamation(1, 2), 1 ) ) => assert!(true),
+            _ => assert!(false)
+        }
+    }
+
     #[test]
     fn operator_or_delimiter_bit_invert() {
         let lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("Unused!"), 4);
@@ -1082,4 +2577,1361 @@ mod tests {
             _ => assert!(false)
         }
     }
+
+    #[test]
+    fn tokenize_empty_source() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from(""), 4);
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens.len(), 1);
+        match &*tokens[0] {
+            TokenSymbol::PyEof(0, 0) => assert!(true),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_simple_assignment() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("a = 1\n"), 4);
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens.len(), 5);
+        match ( &*tokens[0], &*tokens[1], &*tokens[2], &*tokens[3], &*tokens[4] ) {
+            ( TokenSymbol::PyName(0, 1, name), TokenSymbol::PyAssign(2, 3), TokenSymbol::PyNumber(4, 5, number), TokenSymbol::PyNewline(5, 6), TokenSymbol::PyEof(6, 6) ) => {
+                assert_eq!(&**name, "a");
+                assert_eq!(&**number, "1")
+            },
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_keywords_and_implicit_newline_at_end() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("if True:"), 4);
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens.len(), 5);
+        match ( &*tokens[0], &*tokens[1], &*tokens[2], &*tokens[3], &*tokens[4] ) {
+            ( TokenSymbol::PyIf(0, 2), TokenSymbol::PyTrue(3, 7), TokenSymbol::PyColon(7, 8), TokenSymbol::PyNewline(8, 8), TokenSymbol::PyEof(8, 8) ) => assert!(true),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_operators_longest_match() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("x**=y->z"), 4);
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens.len(), 7);
+        match ( &*tokens[1], &*tokens[3] ) {
+            ( TokenSymbol::PyPowerAssign(1, 4), TokenSymbol::PyArrow(5, 7) ) => assert!(true),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_float_number() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("3.14"), 4);
+        let tokens = lexer.tokenize().unwrap();
+        match &*tokens[0] {
+            TokenSymbol::PyNumber(0, 4, text) => assert_eq!(&**text, "3.14"),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_string_with_escaped_quote() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("'a\\'b'"), 4);
+        let tokens = lexer.tokenize().unwrap();
+        match &*tokens[0] {
+            TokenSymbol::PyString(0, 6, parts) => {
+                assert_eq!(parts.len(), 1);
+                assert_eq!(&*parts[0], "'a\\'b'")
+            },
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_skips_comments_and_blank_lines() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("# comment\n\npass # trailing\n"), 4);
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens.len(), 3);
+        match ( &*tokens[0], &*tokens[1], &*tokens[2] ) {
+            ( TokenSymbol::PyPass(11, 15), TokenSymbol::PyNewline(26, 27), TokenSymbol::PyEof(27, 27) ) => assert!(true),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_no_newline_inside_parenthesis() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("f(a,\n  b)\n"), 4);
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens.len(), 8);
+        match ( &*tokens[5], &*tokens[6] ) {
+            ( TokenSymbol::PyRightParen(8, 9), TokenSymbol::PyNewline(9, 10) ) => assert!(true),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_invalid_character() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("a = $"), 4);
+        match lexer.tokenize() {
+            Err(error) => assert_eq!(error.to_string(), "invalid character '$' (U+0024)"),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_unterminated_string() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("x = 'abc\n"), 4);
+        match lexer.tokenize() {
+            Err(error) => assert_eq!(lexer.error_message(&error), "unterminated string literal (detected at line 1)"),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_indent_and_dedent() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("if x:\n    y\nz\n"), 4);
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens.len(), 11);
+        match ( &*tokens[3], &*tokens[4], &*tokens[5], &*tokens[6], &*tokens[7], &*tokens[8] ) {
+            ( TokenSymbol::PyNewline(5, 6), TokenSymbol::PyIndent(6, 10), TokenSymbol::PyName(10, 11, _), TokenSymbol::PyNewline(11, 12), TokenSymbol::PyDedent(12, 12), TokenSymbol::PyName(12, 13, _) ) => assert!(true),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_multiple_dedent_on_one_line() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("if a:\n  if b:\n    c\nd\n"), 4);
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens.len(), 17);
+        match ( &*tokens[12], &*tokens[13], &*tokens[14] ) {
+            ( TokenSymbol::PyDedent(20, 20), TokenSymbol::PyDedent(20, 20), TokenSymbol::PyName(20, 21, _) ) => assert!(true),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_dedent_flushed_before_eof() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("if a:\n  b"), 4);
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens.len(), 9);
+        match ( &*tokens[6], &*tokens[7], &*tokens[8] ) {
+            ( TokenSymbol::PyNewline(9, 9), TokenSymbol::PyDedent(9, 9), TokenSymbol::PyEof(9, 9) ) => assert!(true),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_blank_and_comment_lines_keep_indentation() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("if a:\n  b\n\n# note\n  c\n"), 4);
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens.len(), 11);
+        match ( &*tokens[7], &*tokens[8], &*tokens[9] ) {
+            ( TokenSymbol::PyName(20, 21, _), TokenSymbol::PyNewline(21, 22), TokenSymbol::PyDedent(22, 22) ) => assert!(true),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_tab_expanded_to_tab_size() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("if a:\n\tif b:\n\t    c\n"), 4);
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens.len(), 15);
+        match ( &*tokens[9], &*tokens[10], &*tokens[12], &*tokens[13] ) {
+            ( TokenSymbol::PyIndent(13, 18), TokenSymbol::PyName(18, 19, _), TokenSymbol::PyDedent(20, 20), TokenSymbol::PyDedent(20, 20) ) => assert!(true),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_inconsistent_tabs_and_spaces_same_level() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("if a:\n        b\n\tc\n"), 8);
+        match lexer.tokenize() {
+            Err(error) => assert_eq!(error.to_string(), "inconsistent use of tabs and spaces in indentation"),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_inconsistent_tabs_and_spaces_indent() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("if a:\n  \tb\n"), 4);
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens.len(), 9);
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("if a:\n    if b:\n\t c\n"), 4);
+        match lexer.tokenize() {
+            Err(error) => assert_eq!(error.to_string(), "inconsistent use of tabs and spaces in indentation"),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_inconsistent_tabs_and_spaces_dedent() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("if a:\n    if b:\n        c\n\td\n"), 4);
+        match lexer.tokenize() {
+            Err(error) => assert_eq!(error.to_string(), "inconsistent use of tabs and spaces in indentation"),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_unindent_not_matching_outer_level() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("if a:\n        b\n\tc\n"), 4);
+        match lexer.tokenize() {
+            Err(error) => assert_eq!(error.to_string(), "unindent does not match any outer indentation level"),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_number_literals() {
+        let source = "0 00 0_0 42 1_000_000 0xFF 0X_ff_1 0o17 0O_7 0b1010 0B_1 1. .5 1.5 1e10 1E-5 2.5e+3 1_0.0_1e1_0 3j 1.5J 09.5 0e0 00j";
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from(source), 4);
+        let tokens = lexer.tokenize().unwrap();
+        let expected : Vec<&str> = source.split(' ').collect();
+        assert_eq!(tokens.len(), expected.len() + 2);
+        for (index, text) in expected.iter().enumerate() {
+            match &*tokens[index] {
+                TokenSymbol::PyNumber(_, _, number) => assert_eq!(&**number, *text),
+                _ => assert!(false)
+            }
+        }
+    }
+
+    #[test]
+    fn tokenize_number_followed_by_keyword() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("1if x else 0b1for"), 4);
+        let tokens = lexer.tokenize().unwrap();
+        match ( &*tokens[0], &*tokens[1], &*tokens[4], &*tokens[5] ) {
+            ( TokenSymbol::PyNumber(0, 1, _), TokenSymbol::PyIf(1, 3), TokenSymbol::PyNumber(11, 14, _), TokenSymbol::PyFor(14, 17) ) => assert!(true),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_number_exponent_without_digits_before_else() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("1else"), 4);
+        let tokens = lexer.tokenize().unwrap();
+        match ( &*tokens[0], &*tokens[1] ) {
+            ( TokenSymbol::PyNumber(0, 1, _), TokenSymbol::PyElse(1, 5) ) => assert!(true),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_invalid_number_literals() {
+        let cases = [
+            ( "0_", "invalid decimal literal" ),
+            ( "1__0", "invalid decimal literal" ),
+            ( "1_", "invalid decimal literal" ),
+            ( "0777", "leading zeros in decimal integer literals are not permitted; use an 0o prefix for octal integers" ),
+            ( "x = 1e", "invalid decimal literal" ),
+            ( "1e+", "invalid decimal literal" ),
+            ( "1.5_", "invalid decimal literal" ),
+            ( "12abc", "invalid decimal literal" ),
+            ( "0x", "invalid hexadecimal literal" ),
+            ( "0xfg", "invalid hexadecimal literal" ),
+            ( "0x_", "invalid hexadecimal literal" ),
+            ( "0o8", "invalid digit '8' in octal literal" ),
+            ( "0o17_9", "invalid digit '9' in octal literal" ),
+            ( "0o1a", "invalid octal literal" ),
+            ( "0b2", "invalid digit '2' in binary literal" ),
+            ( "0b12", "invalid digit '2' in binary literal" ),
+            ( "3jx", "invalid imaginary literal" )
+        ];
+        for ( source, expected ) in cases {
+            let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from(source), 4);
+            match lexer.tokenize() {
+                Err(error) => assert_eq!(error.to_string(), expected),
+                _ => assert!(false)
+            }
+        }
+    }
+
+    #[test]
+    fn tokenize_string_prefixes() {
+        let source = "r'a' R\"a\" u'a' U'a' b'a' B\"a\" br'a' Br'a' bR'a' rb'a' RB'a'";
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from(source), 4);
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens.len(), 3);
+        match &*tokens[0] {
+            TokenSymbol::PyString(0, end, parts) => {
+                assert_eq!(*end as usize, source.len());
+                let expected : Vec<&str> = source.split(' ').collect();
+                assert_eq!(parts.len(), expected.len());
+                for (index, text) in expected.iter().enumerate() {
+                    assert_eq!(parts[index].trim_start(), *text)
+                }
+            },
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_fstring_prefixes() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("f'a' F'a' fr'a' rF'a' Rf'a' FR'a'"), 4);
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens.len(), 20);
+        for index in 0 .. 6 {
+            match ( &*tokens[index * 3], &*tokens[index * 3 + 2] ) {
+                ( TokenSymbol::PyFStringStart(_, _, _), TokenSymbol::PyFStringEnd(_, _, _) ) => assert!(true),
+                _ => assert!(false)
+            }
+        }
+    }
+
+    #[test]
+    fn tokenize_invalid_string_prefix_is_name() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("ub'a'"), 4);
+        let tokens = lexer.tokenize().unwrap();
+        match ( &*tokens[0], &*tokens[1] ) {
+            ( TokenSymbol::PyName(0, 2, name), TokenSymbol::PyString(2, 5, _) ) => assert_eq!(&**name, "ub"),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_triple_quoted_string() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("x = '''a\n'b'\n''c'''\n"), 4);
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens.len(), 5);
+        match ( &*tokens[2], &*tokens[3] ) {
+            ( TokenSymbol::PyString(4, 19, parts), TokenSymbol::PyNewline(19, 20) ) => assert_eq!(&*parts[0], "'''a\n'b'\n''c'''"),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_escaped_newline_in_single_quoted_string() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("'a\\\nb'"), 4);
+        let tokens = lexer.tokenize().unwrap();
+        match &*tokens[0] {
+            TokenSymbol::PyString(0, 6, _) => assert!(true),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_implicit_string_concatenation() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("x = ('a' \"b\"\n     '''c''')\n'd'\n'e'"), 4);
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens.len(), 11);
+        match ( &*tokens[3], &*tokens[6], &*tokens[8] ) {
+            ( TokenSymbol::PyString(5, 25, parts), TokenSymbol::PyString(27, 30, _), TokenSymbol::PyString(31, 34, _) ) => {
+                assert_eq!(parts.len(), 3);
+                assert_eq!(&*parts[0], "'a'");
+                assert_eq!(&*parts[1], " \"b\"");
+                assert_eq!(&*parts[2], "\n     '''c'''")
+            },
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_unterminated_triple_quoted_string() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("x = 1\ny = \"\"\"abc\n\""), 4);
+        match lexer.tokenize() {
+            Err(error) => assert_eq!(lexer.error_message(&error), "unterminated triple-quoted string literal (detected at line 3)"),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_unterminated_string_at_end_of_file() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("b'abc\\"), 4);
+        match lexer.tokenize() {
+            Err(error) => assert_eq!(lexer.error_message(&error), "unterminated string literal (detected at line 1)"),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_fstring_with_conversion_and_nested_format_spec() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("f\"abc{x!r:>{width}}def\""), 4);
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens.len(), 16);
+        match ( &*tokens[0], &*tokens[1], &*tokens[2], &*tokens[3], &*tokens[4], &*tokens[5], &*tokens[6] ) {
+            ( TokenSymbol::PyFStringStart(0, 2, start), TokenSymbol::PyFStringMiddle(2, 5, middle), TokenSymbol::PyLeftCurly(5, 6), TokenSymbol::PyName(6, 7, _),
+              TokenSymbol::PyExclamation(7, 8), TokenSymbol::PyName(8, 9, _), TokenSymbol::PyColon(9, 10) ) => {
+                assert_eq!(&**start, "f\"");
+                assert_eq!(&**middle, "abc")
+            },
+            _ => assert!(false)
+        }
+        match ( &*tokens[7], &*tokens[8], &*tokens[9], &*tokens[10], &*tokens[11], &*tokens[12], &*tokens[13] ) {
+            ( TokenSymbol::PyFStringMiddle(10, 11, spec), TokenSymbol::PyLeftCurly(11, 12), TokenSymbol::PyName(12, 17, _), TokenSymbol::PyRightCurly(17, 18),
+              TokenSymbol::PyRightCurly(18, 19), TokenSymbol::PyFStringMiddle(19, 22, middle), TokenSymbol::PyFStringEnd(22, 23, end) ) => {
+                assert_eq!(&**spec, ">");
+                assert_eq!(&**middle, "def");
+                assert_eq!(&**end, "\"")
+            },
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_fstring_with_nested_quotes() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("f\"{'nested \"quotes\"'}\""), 4);
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens.len(), 7);
+        match ( &*tokens[0], &*tokens[1], &*tokens[2], &*tokens[3], &*tokens[4] ) {
+            ( TokenSymbol::PyFStringStart(0, 2, _), TokenSymbol::PyLeftCurly(2, 3), TokenSymbol::PyString(3, 20, parts), TokenSymbol::PyRightCurly(20, 21), TokenSymbol::PyFStringEnd(21, 22, _) ) => {
+                assert_eq!(&*parts[0], "'nested \"quotes\"'")
+            },
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_fstring_nested_with_same_quotes() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("f\"{f\"{1}\"}\""), 4);
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens.len(), 11);
+        match ( &*tokens[2], &*tokens[3], &*tokens[4], &*tokens[5], &*tokens[6], &*tokens[7], &*tokens[8] ) {
+            ( TokenSymbol::PyFStringStart(3, 5, _), TokenSymbol::PyLeftCurly(5, 6), TokenSymbol::PyNumber(6, 7, _), TokenSymbol::PyRightCurly(7, 8),
+              TokenSymbol::PyFStringEnd(8, 9, _), TokenSymbol::PyRightCurly(9, 10), TokenSymbol::PyFStringEnd(10, 11, _) ) => assert!(true),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_fstring_escaped_braces() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("f\"a{{b}}c\""), 4);
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens.len(), 7);
+        match ( &*tokens[1], &*tokens[2], &*tokens[3], &*tokens[4] ) {
+            ( TokenSymbol::PyFStringMiddle(2, 5, first), TokenSymbol::PyFStringMiddle(5, 8, second), TokenSymbol::PyFStringMiddle(8, 9, third), TokenSymbol::PyFStringEnd(9, 10, _) ) => {
+                assert_eq!(&**first, "a{");
+                assert_eq!(&**second, "b}");
+                assert_eq!(&**third, "c")
+            },
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_fstring_format_spec_starting_with_assign() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("f\"{x:=10}\""), 4);
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens.len(), 9);
+        match ( &*tokens[3], &*tokens[4], &*tokens[5] ) {
+            ( TokenSymbol::PyColon(4, 5), TokenSymbol::PyFStringMiddle(5, 8, spec), TokenSymbol::PyRightCurly(8, 9) ) => assert_eq!(&**spec, "=10"),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_fstring_operators_inside_field() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("f\"{a!=b} { {'k': 1}['k'] }\""), 4);
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens.len(), 20);
+        match ( &*tokens[3], &*tokens[6], &*tokens[10], &*tokens[11], &*tokens[16], &*tokens[17] ) {
+            ( TokenSymbol::PyNotEqual(4, 6), TokenSymbol::PyFStringMiddle(8, 9, _), TokenSymbol::PyColon(15, 16), TokenSymbol::PyNumber(17, 18, _),
+              TokenSymbol::PyRightCurly(25, 26), TokenSymbol::PyFStringEnd(26, 27, _) ) => assert!(true),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_triple_quoted_raw_fstring() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("rf'''\\d\n{x}'''"), 4);
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens.len(), 8);
+        match ( &*tokens[0], &*tokens[1], &*tokens[2], &*tokens[4], &*tokens[5] ) {
+            ( TokenSymbol::PyFStringStart(0, 5, start), TokenSymbol::PyFStringMiddle(5, 8, middle), TokenSymbol::PyLeftCurly(8, 9), TokenSymbol::PyRightCurly(10, 11), TokenSymbol::PyFStringEnd(11, 14, _) ) => {
+                assert_eq!(&**start, "rf'''");
+                assert_eq!(&**middle, "\\d\n")
+            },
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_fstring_not_concatenated_with_string() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("'a' f'b' 'c'"), 4);
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens.len(), 7);
+        match ( &*tokens[0], &*tokens[1], &*tokens[3], &*tokens[4] ) {
+            ( TokenSymbol::PyString(0, 3, _), TokenSymbol::PyFStringStart(4, 6, _), TokenSymbol::PyFStringEnd(7, 8, _), TokenSymbol::PyString(9, 12, _) ) => assert!(true),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_fstring_field_over_lines_with_comments() {
+        let source = "f\"{\n  a, # one\n  b  # two\n}\"\n";
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from(source), 4).with_comments(true).with_non_logical_newlines(true);
+        let tokens = lexer.tokenize().unwrap();
+        let kinds : Vec<(TokenKind, (u32, u32))> = tokens.iter().map(|symbol| ( symbol.kind(), symbol.offsets() )).collect();
+        assert_eq!(kinds, vec![
+            ( TokenKind::PyFStringStart, ( 0, 2 ) ), ( TokenKind::PyLeftCurly, ( 2, 3 ) ), ( TokenKind::PyNl, ( 3, 4 ) ),
+            ( TokenKind::PyName, ( 6, 7 ) ), ( TokenKind::PyComma, ( 7, 8 ) ), ( TokenKind::PyComment, ( 9, 14 ) ), ( TokenKind::PyNl, ( 14, 15 ) ),
+            ( TokenKind::PyName, ( 17, 18 ) ), ( TokenKind::PyComment, ( 20, 25 ) ), ( TokenKind::PyNl, ( 25, 26 ) ),
+            ( TokenKind::PyRightCurly, ( 26, 27 ) ), ( TokenKind::PyFStringEnd, ( 27, 28 ) ), ( TokenKind::PyNewline, ( 28, 29 ) ), ( TokenKind::PyEof, ( 29, 29 ) )
+        ])
+    }
+
+    #[test]
+    fn tokenize_invalid_fstrings() {
+        let cases = [
+            ( "f\"abc", "unterminated f-string literal (detected at line 1)" ),
+            ( "f'''abc\n", "unterminated triple-quoted f-string literal (detected at line 1)" ),
+            ( "f\"{x", "unterminated f-string literal (detected at line 1)" ),
+            ( "f\"a}\"", "f-string: single '}' is not allowed" ),
+            ( "f\"{x:\"", "f-string: expecting '}'" ),
+            ( "f\"{x # comment}\"", "unterminated f-string literal (detected at line 1)" )
+        ];
+        for ( source, expected ) in cases {
+            let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from(source), 4);
+            match lexer.tokenize() {
+                Err(error) => assert_eq!(lexer.error_message(&error), expected),
+                _ => assert!(false)
+            }
+        }
+    }
+
+    #[test]
+    fn tokenize_unicode_names() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("café = 变量 + _π1"), 4);
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens.len(), 7);
+        match ( &*tokens[0], &*tokens[2], &*tokens[4] ) {
+            ( TokenSymbol::PyName(0, 4, first), TokenSymbol::PyName(7, 9, second), TokenSymbol::PyName(12, 15, third) ) => {
+                assert_eq!(&**first, "café");
+                assert_eq!(&**second, "变量");
+                assert_eq!(&**third, "_π1")
+            },
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_name_is_nfkc_normalized() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("ﬁle ℌ"), 4);
+        let tokens = lexer.tokenize().unwrap();
+        match ( &*tokens[0], &*tokens[1] ) {
+            ( TokenSymbol::PyName(0, 3, first), TokenSymbol::PyName(4, 5, second) ) => {
+                assert_eq!(&**first, "file");
+                assert_eq!(&**second, "H")
+            },
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_invalid_character_in_name() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("a€b = 1"), 4);
+        match lexer.tokenize() {
+            Err(error) => assert_eq!(error.to_string(), "invalid character '€' (U+20AC)"),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_invalid_start_character_in_name() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("x = ١٢"), 4);
+        match lexer.tokenize() {
+            Err(error) => assert_eq!(error.to_string(), "invalid character '١' (U+0661)"),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_invalid_non_printable_character() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("a\u{a0}= 1"), 4);
+        match lexer.tokenize() {
+            Err(error) => assert_eq!(error.to_string(), "invalid non-printable character U+00A0"),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_soft_keywords() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("match x:\n    case _:\n        type = _x\n"), 4);
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens.len(), 17);
+        match ( &*tokens[0], &*tokens[5], &*tokens[6], &*tokens[10], &*tokens[12] ) {
+            ( TokenSymbol::PyMatch(0, 5), TokenSymbol::PyCase(13, 17), TokenSymbol::PyUnderscore(18, 19), TokenSymbol::PyType(29, 33), TokenSymbol::PyName(36, 38, _) ) => assert!(true),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_span_of_tokens() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("if x:\n    s = 'é😀'\n"), 4);
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens.len(), 11);
+        let indent = lexer.span(&tokens[4]);
+        assert_eq!(indent.start, Position { line: 2, column: 0, utf8_column: 0, utf16_column: 0 });
+        assert_eq!(indent.end, Position { line: 2, column: 4, utf8_column: 4, utf16_column: 4 });
+        let string = lexer.span(&tokens[7]);
+        assert_eq!(string.start, Position { line: 2, column: 8, utf8_column: 8, utf16_column: 8 });
+        assert_eq!(string.end, Position { line: 2, column: 12, utf8_column: 16, utf16_column: 13 });
+        let newline = lexer.span(&tokens[8]);
+        assert_eq!(newline.end, Position { line: 2, column: 13, utf8_column: 17, utf16_column: 14 });
+        let eof = lexer.span(&tokens[10]);
+        assert_eq!(eof.start, Position { line: 3, column: 0, utf8_column: 0, utf16_column: 0 });
+        assert_eq!(lexer.position(1), Position { line: 1, column: 1, utf8_column: 1, utf16_column: 1 })
+    }
+
+    #[test]
+    fn tokenize_type_comment() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("x = [] # type: List[int]\n# plain\n"), 4).with_type_comments(true);
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens.len(), 7);
+        match ( &*tokens[4], &*tokens[5], &*tokens[6] ) {
+            ( TokenSymbol::PyTypeComment(7, 24, text), TokenSymbol::PyNewline(24, 25), TokenSymbol::PyEof(33, 33) ) => assert_eq!(&**text, "List[int]"),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_type_ignore_comment() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("import a  #type:ignore[attr-defined] \r\n"), 4).with_type_comments(true);
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens.len(), 5);
+        match ( &*tokens[2], &*tokens[3] ) {
+            ( TokenSymbol::PyTypeComment(10, 37, text), TokenSymbol::PyNewline(37, 39) ) => assert_eq!(&**text, "ignore[attr-defined]"),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_type_comment_is_skipped_by_default() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("x = 1 # type: int\n"), 4);
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens.len(), 5);
+        match &*tokens[3] {
+            TokenSymbol::PyNewline(17, 18) => assert!(true),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_comments() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("# hello\nx = 1 # type: int\n"), 4).with_comments(true);
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens.len(), 7);
+        match ( &*tokens[0], &*tokens[1], &*tokens[4], &*tokens[5] ) {
+            ( TokenSymbol::PyComment(0, 7, first), TokenSymbol::PyName(8, 9, _), TokenSymbol::PyComment(14, 25, second), TokenSymbol::PyNewline(25, 26) ) => {
+                assert_eq!(&**first, "# hello");
+                assert_eq!(&**second, "# type: int")
+            },
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_comments_with_type_comments() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("x = 1 # type: int\n# other"), 4).with_comments(true).with_type_comments(true);
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens.len(), 7);
+        match ( &*tokens[3], &*tokens[5] ) {
+            ( TokenSymbol::PyTypeComment(6, 17, first), TokenSymbol::PyComment(18, 25, second) ) => {
+                assert_eq!(&**first, "int");
+                assert_eq!(&**second, "# other")
+            },
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_implicit_line_joining() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("x = (1,\n  2)\n"), 4);
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens.len(), 9);
+        match ( &*tokens[4], &*tokens[5], &*tokens[7] ) {
+            ( TokenSymbol::PyComma(6, 7), TokenSymbol::PyNumber(10, 11, _), TokenSymbol::PyNewline(12, 13) ) => assert!(true),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_explicit_line_joining() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("if x:\n    y = 1 + \\\n2\n"), 4);
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens.len(), 13);
+        match ( &*tokens[9], &*tokens[10], &*tokens[11], &*tokens[12] ) {
+            ( TokenSymbol::PyNumber(20, 21, _), TokenSymbol::PyNewline(21, 22), TokenSymbol::PyDedent(22, 22), TokenSymbol::PyEof(22, 22) ) => assert!(true),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_non_logical_newlines() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("\n# c\nx = [\n1]\n"), 4).with_non_logical_newlines(true);
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens.len(), 10);
+        match ( &*tokens[0], &*tokens[1], &*tokens[5], &*tokens[8] ) {
+            ( TokenSymbol::PyNl(0, 1), TokenSymbol::PyNl(4, 5), TokenSymbol::PyNl(10, 11), TokenSymbol::PyNewline(13, 14) ) => assert!(true),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_non_logical_newlines_are_skipped_by_default() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("\n# c\nx = [\n1]\n"), 4);
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens.len(), 7);
+    }
+
+    #[test]
+    fn tokenize_string_concatenation_across_continuation() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("'a' \\\n'b'"), 4);
+        let tokens = lexer.tokenize().unwrap();
+        match &*tokens[0] {
+            TokenSymbol::PyString(0, 9, parts) => assert_eq!(parts.join(""), "'a' \\\n'b'"),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_unexpected_eof_in_multi_line_statement() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("x = (1,\n2\n"), 4);
+        match lexer.tokenize() {
+            Err(error) => assert_eq!(error.to_string(), "unexpected EOF in multi-line statement"),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_continuation_at_end_of_file() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("x = 1 + \\\n"), 4);
+        match lexer.tokenize() {
+            Err(error) => assert_eq!(error.to_string(), "unexpected EOF in multi-line statement"),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_unexpected_character_after_continuation() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("x = 1 \\ + 2\n"), 4);
+        match lexer.tokenize() {
+            Err(error) => assert_eq!(error.to_string(), "unexpected character after line continuation character"),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_unmatched_closing_bracket() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("x = 1)\n"), 4);
+        match lexer.tokenize() {
+            Err(error) => assert_eq!(error.to_string(), "unmatched ')'"),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_mismatched_closing_bracket() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("x = [1,\n2)\n"), 4);
+        match lexer.tokenize() {
+            Err(error) => assert_eq!(error.to_string(), "closing parenthesis ')' does not match opening parenthesis '[' on line 1"),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_too_many_nested_parentheses() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new("(".repeat(201), 4);
+        match lexer.tokenize() {
+            Err(error) => assert_eq!(error.to_string(), "too many nested parentheses"),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_lossless_trivia() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("x  = 1 # one\n\ny = \\\n  2\n"), 4);
+        let tokens = lexer.tokenize_lossless().unwrap();
+        assert_eq!(tokens.len(), 9);
+        assert_eq!(&*tokens[1].trivia, "  ");
+        assert_eq!(&*tokens[3].trivia, " # one");
+        assert_eq!(&*tokens[4].trivia, "\n");
+        assert_eq!(&*tokens[4].text, "y");
+        assert_eq!(&*tokens[6].trivia, " \\\n  ");
+        match ( &tokens[6].symbol, &*tokens[6].text ) {
+            ( TokenSymbol::PyNumber(22, 23, _), "2" ) => assert!(true),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_lossless_trailing_trivia_on_eof() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("pass\n  # end\n\n"), 4);
+        let tokens = lexer.tokenize_lossless().unwrap();
+        match tokens.last() {
+            Some(LosslessToken { trivia, symbol: TokenSymbol::PyEof(_, _), .. }) => assert_eq!(&**trivia, "  # end\n\n"),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn untokenize_round_trip() {
+        let source = "# -*- coding: utf-8 -*-\r\nclass A:\r\n\tdef f(self, x=(1,\r\n\t\t2)):  # type: ignore\r\n\t\treturn f'{x!r:>{10}} {{y}}' 'a' \\\r\n\t\t\tb\"\"\"c\"\"\"\r\n\r\n\tpass\r\n";
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from(source), 4);
+        let tokens = lexer.tokenize_lossless().unwrap();
+        assert_eq!(untokenize(&tokens), source);
+    }
+
+    #[test]
+    fn untokenize_round_trip_with_comment_and_nl_tokens() {
+        let source = "if x:\n    # body\n    ẞ = [1,\n  2]  \n# end";
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from(source), 4).with_comments(true).with_non_logical_newlines(true);
+        let tokens = lexer.tokenize_lossless().unwrap();
+        assert_eq!(untokenize(&tokens), source);
+    }
+
+    #[test]
+    fn tokenize_from_bytes_with_coding_cookie() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::from_bytes(b"# -*- coding: latin-1 -*-\n\xe5 = 1\n", 4).unwrap();
+        let tokens = lexer.tokenize().unwrap();
+        match &*tokens[0] {
+            TokenSymbol::PyName(26, 27, text) => assert_eq!(&**text, "å"),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_from_bytes_skips_bom() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::from_bytes(b"\xef\xbb\xbfpass\n", 4).unwrap();
+        let tokens = lexer.tokenize().unwrap();
+        match &*tokens[0] {
+            TokenSymbol::PyPass(0, 4) => assert!(true),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_from_bytes_with_bom_and_cookie_conflict() {
+        match PythonCoreTokenizer::from_bytes(b"\xef\xbb\xbf# coding: cp1252\npass\n", 4) {
+            Err(error) => assert_eq!(error.to_string(), "encoding problem: cp1252 with BOM"),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_error_variants_and_offsets() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("x = 'abc\n"), 4);
+        match lexer.tokenize() {
+            Err(TokenizerError::UnterminatedString(4, 8, false)) => assert!(true),
+            _ => assert!(false)
+        }
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("x = 0o18\n"), 4);
+        match lexer.tokenize() {
+            Err(error) => {
+                assert_eq!(error, TokenizerError::InvalidDigit(7, 8, '8', "octal"));
+                assert_eq!(error.offsets(), ( 7, 8 ))
+            },
+            _ => assert!(false)
+        }
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("(]"), 4);
+        match lexer.tokenize() {
+            Err(TokenizerError::MismatchedBracket(1, 2, ']', '(', None)) => assert!(true),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_error_is_std_error() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("x = $\n"), 4);
+        let error : Box<dyn std::error::Error> = Box::new(lexer.tokenize().err().unwrap());
+        assert_eq!(error.to_string(), "invalid character '$' (U+0024)")
+    }
+
+    #[test]
+    fn tokenize_error_recovery_invalid_character() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("x = $ + 1\n"), 4).with_error_recovery(true);
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens.len(), 7);
+        match ( &*tokens[2], &*tokens[3], &*tokens[4] ) {
+            ( TokenSymbol::PyError(4, 5, text), TokenSymbol::PyPlus(6, 7), TokenSymbol::PyNumber(8, 9, _) ) => assert_eq!(&**text, "$"),
+            _ => assert!(false)
+        }
+        assert_eq!(lexer.diagnostics(), &[ TokenizerError::InvalidCharacter(4, 5, '$') ])
+    }
+
+    #[test]
+    fn tokenize_error_recovery_unterminated_string() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("x = 'abc\ny = 2\n"), 4).with_error_recovery(true);
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens.len(), 9);
+        match ( &*tokens[2], &*tokens[3], &*tokens[4] ) {
+            ( TokenSymbol::PyError(4, 8, text), TokenSymbol::PyNewline(8, 9), TokenSymbol::PyName(9, 10, _) ) => assert_eq!(&**text, "'abc"),
+            _ => assert!(false)
+        }
+        assert_eq!(lexer.diagnostics().len(), 1)
+    }
+
+    #[test]
+    fn tokenize_error_recovery_invalid_number_and_bracket() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("f(0o18x)]\n"), 4).with_error_recovery(true);
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens.len(), 7);
+        match ( &*tokens[2], &*tokens[3], &*tokens[4] ) {
+            ( TokenSymbol::PyError(2, 7, text), TokenSymbol::PyRightParen(7, 8), TokenSymbol::PyError(8, 9, _) ) => assert_eq!(&**text, "0o18x"),
+            _ => assert!(false)
+        }
+        assert_eq!(lexer.diagnostics(), &[ TokenizerError::InvalidDigit(5, 6, '8', "octal"), TokenizerError::UnmatchedBracket(8, 9, ']') ])
+    }
+
+    #[test]
+    fn tokenize_error_recovery_unterminated_fstring_and_open_bracket() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("s = f'abc\nt = (1,\n"), 4).with_error_recovery(true);
+        let tokens = lexer.tokenize().unwrap();
+        match ( &*tokens[3], &*tokens[4], &*tokens[5] ) {
+            ( TokenSymbol::PyError(6, 9, text), TokenSymbol::PyNewline(9, 10), TokenSymbol::PyName(10, 11, _) ) => assert_eq!(&**text, "abc"),
+            _ => assert!(false)
+        }
+        match tokens.last() {
+            Some(symbol) => assert!(matches!(**symbol, TokenSymbol::PyEof(_, _))),
+            _ => assert!(false)
+        }
+        assert_eq!(lexer.diagnostics().len(), 2);
+        assert!(matches!(lexer.diagnostics()[1], TokenizerError::UnexpectedEof(14, _)))
+    }
+
+    #[test]
+    fn tokenize_error_recovery_unclosed_bracket_before_statement() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("x = f(1,\ndef g():\n    return 1 $ 2\n"), 4).with_error_recovery(true);
+        let tokens = lexer.tokenize().unwrap();
+        match ( &*tokens[6], &*tokens[7], &*tokens[8], &*tokens[14] ) {
+            ( TokenSymbol::PyError(9, 9, _), TokenSymbol::PyNewline(9, 9), TokenSymbol::PyDef(9, 12), TokenSymbol::PyIndent(_, _) ) => assert!(true),
+            _ => assert!(false)
+        }
+        assert_eq!(lexer.diagnostics(), &[ TokenizerError::UnclosedBracket(5, 6, '('), TokenizerError::InvalidCharacter(31, 32, '$') ]);
+        assert_eq!(lexer.diagnostics()[0].to_string(), "'(' was never closed");
+
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("x = [a\nfor a in b]\n"), 4).with_error_recovery(true);
+        lexer.tokenize().unwrap();
+        assert!(lexer.diagnostics().is_empty())
+    }
+
+    #[test]
+    fn tokenize_error_recovery_indentation() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("if x:\n    y\n  z\n"), 4).with_error_recovery(true);
+        let tokens = lexer.tokenize().unwrap();
+        assert!(tokens.iter().any(|symbol| matches!(**symbol, TokenSymbol::PyError(14, 14, _))));
+        assert_eq!(lexer.diagnostics(), &[ TokenizerError::UnindentMismatch(14, 14) ])
+    }
+
+    #[test]
+    fn tokenize_as_stream() {
+        let lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("if x:\n    pass\n"), 4);
+        let tokens : Vec<TokenSymbol> = lexer.into_iter().map(|symbol| symbol.unwrap()).collect();
+        assert_eq!(tokens.len(), 9);
+        match ( &tokens[4], &tokens[7], &tokens[8] ) {
+            ( TokenSymbol::PyIndent(6, 10), TokenSymbol::PyDedent(15, 15), TokenSymbol::PyEof(15, 15) ) => assert!(true),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_as_stream_ends_after_error() {
+        let lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("x = $\ny\n"), 4);
+        let mut stream = lexer.into_iter();
+        assert!(matches!(stream.next(), Some(Ok(TokenSymbol::PyName(0, 1, _)))));
+        assert!(matches!(stream.next(), Some(Ok(TokenSymbol::PyAssign(2, 3)))));
+        assert!(matches!(stream.next(), Some(Err(TokenizerError::InvalidCharacter(4, 5, '$')))));
+        assert!(stream.next().is_none());
+        assert!(stream.next().is_none())
+    }
+
+    #[test]
+    fn tokenize_as_stream_with_error_recovery() {
+        let lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("x = $\n"), 4).with_error_recovery(true);
+        let mut stream = lexer.into_iter();
+        let count = stream.by_ref().filter(|symbol| symbol.is_ok()).count();
+        assert_eq!(count, 5);
+        assert_eq!(stream.tokenizer().diagnostics().len(), 1)
+    }
+
+    #[test]
+    fn tokenize_borrowed_slices_with_byte_offsets() {
+        let source = "ﬁx = 'å' + 0x1F  # type: int\n";
+        let mut lexer = BorrowedTokenizer::borrowing(source, 4).with_type_comments(true);
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens.len(), 8);
+        match ( &tokens[0], &tokens[2], &tokens[4], &tokens[5] ) {
+            ( TokenSymbol::PyName(0, 4, name), TokenSymbol::PyString(7, 11, parts), TokenSymbol::PyNumber(14, 18, number), TokenSymbol::PyTypeComment(20, 31, kind) ) => {
+                assert_eq!(*name, "ﬁx");
+                assert_eq!(parts[0], "'å'");
+                assert_eq!(*number, "0x1F");
+                assert_eq!(*kind, "int")
+            },
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_borrowed_fstring_middle() {
+        let mut lexer = BorrowedTokenizer::borrowing("f'ø{{x}}{y}'", 4);
+        let tokens = lexer.tokenize().unwrap();
+        match ( &tokens[1], &tokens[2], &tokens[3] ) {
+            ( TokenSymbol::PyFStringMiddle(2, 6, first), TokenSymbol::PyFStringMiddle(6, 9, second), TokenSymbol::PyLeftCurly(9, 10) ) => {
+                assert_eq!(*first, "ø{");
+                assert_eq!(*second, "x}")
+            },
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_borrowed_error_offsets() {
+        let mut lexer = BorrowedTokenizer::borrowing("å = 1 €\n", 4);
+        match lexer.tokenize() {
+            Err(error) => assert_eq!(error, TokenizerError::InvalidCharacter(7, 10, '€')),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn span_of_borrowed_tokens_same_as_owned() {
+        let source = "if x:\n    s = 'é😀' # ø\r\n";
+        let mut borrowed = BorrowedTokenizer::borrowing(source, 4).with_comments(true);
+        let borrowed_tokens = borrowed.tokenize().unwrap();
+        let mut owned : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from(source), 4).with_comments(true);
+        let owned_tokens = owned.tokenize().unwrap();
+        assert_eq!(borrowed_tokens.len(), owned_tokens.len());
+        for ( symbol, owned_symbol ) in borrowed_tokens.iter().zip(owned_tokens.iter()) {
+            assert_eq!(borrowed.span(symbol), owned.span(owned_symbol))
+        }
+        assert_eq!(borrowed.position(17), owned.position(16))
+    }
+
+    #[test]
+    fn debug_tokens_with_lines_and_columns() {
+        let source = "x = 1\ny **= 'å'\n";
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from(source), 4);
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(format!("{:?}", tokens[5]), "OP '**=' 8-11");
+        assert_eq!(format!("{:?}", &lexer.debug_tokens(&tokens)[4 .. 8]), "[NAME 'y' 2:0-2:1, OP '**=' 2:2-2:5, STRING \"'å'\" 2:6-2:9, NEWLINE '\\n' 2:9-2:10]");
+        let mut borrowed = BorrowedTokenizer::borrowing(source, 4);
+        let borrowed_tokens = borrowed.tokenize().unwrap();
+        assert_eq!(borrowed.debug_tokens(&borrowed_tokens), lexer.debug_tokens(&tokens))
+    }
+
+    #[test]
+    fn tokenize_borrowed_as_stream() {
+        let lexer = BorrowedTokenizer::borrowing("if x:\n    pass\n", 4);
+        assert_eq!(lexer.into_iter().filter(|symbol| symbol.is_ok()).count(), 9)
+    }
+
+    #[test]
+    fn retokenize_edit_inside_line() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("a = 1\nb = 2\nc = 3\n"), 4);
+        let previous = lexer.tokenize().unwrap();
+        let tokens = lexer.retokenize(&previous, 10, 11, "200").unwrap();
+        assert_eq!(tokens.len(), 13);
+        match ( &*tokens[4], &*tokens[5], &*tokens[6], &*tokens[9], &*tokens[12] ) {
+            ( TokenSymbol::PyName(6, 7, _), TokenSymbol::PyAssign(8, 9), TokenSymbol::PyNumber(10, 13, number), TokenSymbol::PyAssign(16, 17), TokenSymbol::PyEof(20, 20) ) => {
+                assert_eq!(&**number, "200")
+            },
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn retokenize_edit_changing_indentation() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("if a:\n    b\nc\n"), 4);
+        let previous = lexer.tokenize().unwrap();
+        let tokens = lexer.retokenize(&previous, 12, 12, "    ").unwrap();
+        assert_eq!(tokens.len(), 11);
+        match ( &*tokens[7], &*tokens[8], &*tokens[9], &*tokens[10] ) {
+            ( TokenSymbol::PyName(16, 17, _), TokenSymbol::PyNewline(17, 18), TokenSymbol::PyDedent(18, 18), TokenSymbol::PyEof(18, 18) ) => assert!(true),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn retokenize_edit_opening_string() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("a = 1\nb = 2\n"), 4);
+        let previous = lexer.tokenize().unwrap();
+        match lexer.retokenize(&previous, 6, 6, "'''") {
+            Err(error) => assert_eq!(lexer.error_message(&error), "unterminated triple-quoted string literal (detected at line 2)"),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn retokenize_keeps_diagnostics_in_front_of_edit() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("a = $\nb = 2\n"), 4).with_error_recovery(true);
+        let previous = lexer.tokenize().unwrap();
+        let tokens = lexer.retokenize(&previous, 10, 11, "?").unwrap();
+        assert_eq!(tokens.len(), 9);
+        assert_eq!(lexer.diagnostics(), &[ TokenizerError::InvalidCharacter(4, 5, '$'), TokenizerError::InvalidCharacter(10, 11, '?') ])
+    }
+
+    #[test]
+    fn retokenize_edit_after_unclosed_bracket() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("[a    \ndef f']"), 4).with_error_recovery(true);
+        let previous = lexer.tokenize().unwrap();
+        assert!(matches!(*previous[2], TokenSymbol::PyError(7, 7, _)));
+        let tokens = lexer.retokenize(&previous, 8, 11, "a=").unwrap();
+        let diagnostics = lexer.diagnostics().to_vec();
+        let mut full : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("[a    \nda=f']"), 4).with_error_recovery(true);
+        assert_eq!(tokens, full.tokenize().unwrap());
+        assert_eq!(diagnostics, full.diagnostics());
+        assert_eq!(diagnostics, &[ TokenizerError::UnterminatedFString(10, 13, false), TokenizerError::UnexpectedEof(0, 13) ])
+    }
+
+    #[test]
+    fn interactive_complete_statement() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("x = (1,\n 2)\n"), 4);
+        match lexer.tokenize_interactive() {
+            Ok(InteractiveInput::Complete(tokens)) => assert_eq!(tokens.len(), 9),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn interactive_incomplete_inputs() {
+        for source in [ "x = (1,\n", "s = '''abc\n", "f\"\"\"{x}\n", "x = 1 + \\\n", "if x:\n", "if x: # check\n", "while x:\n    x -= 1\n", "@dec\n", "@dec(1)\n@other\n", "class C:\n    @property\n", "if x: pass\n", "def f(): pass\n", "for x in y: pass\n", "class C: pass # c\n", "if x:\n    pass\nelse: pass\n" ] {
+            let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from(source), 4);
+            match lexer.tokenize_interactive() {
+                Ok(InteractiveInput::Incomplete) => assert!(true),
+                _ => assert!(false, "{}", source)
+            }
+        }
+    }
+
+    #[test]
+    fn interactive_block_ended_by_empty_line() {
+        for source in [ "while x:\n    x -= 1\n\n", "if x:\r\n    pass\r\n  \r\n", "if x: pass\n\n", "def f(): pass\n\n", "for x in y: pass\n  \n", "@dec\n\n", "x = a @ b\n", "x = 1 if y else 2\n" ] {
+            let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from(source), 4);
+            match lexer.tokenize_interactive() {
+                Ok(InteractiveInput::Complete(_)) => assert!(true),
+                _ => assert!(false, "{}", source)
+            }
+        }
+    }
+
+    #[test]
+    fn interactive_errors_are_returned() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("s = 'abc\n"), 4);
+        match lexer.tokenize_interactive() {
+            Err(error) => assert_eq!(lexer.error_message(&error), "unterminated string literal (detected at line 1)"),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn interactive_incomplete_with_error_recovery() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("x = [$\n"), 4).with_error_recovery(true);
+        match lexer.tokenize_interactive() {
+            Ok(InteractiveInput::Incomplete) => assert!(true),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn decode_concatenated_strings() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("x = ('a\\tb'\n     r'\\d' \\\n \"\\N{BULLET}\")\n"), 4);
+        let tokens = lexer.tokenize().unwrap();
+        match lexer.decode_string(&tokens[3]) {
+            Some(Ok(( LiteralValue::Str(text), warnings ))) => {
+                assert_eq!(text, "a\tb\\d•");
+                assert!(warnings.is_empty())
+            },
+            _ => assert!(false)
+        }
+        assert!(lexer.decode_string(&tokens[0]).is_none())
+    }
+
+    #[test]
+    fn decode_fstring_before_python_3_12_gives_none() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("f\"{x}\"\n'a' F'{y}'\nrb'c'\n"), 4).with_python_version(PythonVersion::PY311);
+        let tokens = lexer.tokenize().unwrap();
+        assert!(matches!(*tokens[0], TokenSymbol::PyString(0, 6, _)));
+        assert!(lexer.decode_string(&tokens[0]).is_none());
+        assert!(lexer.decode_string(&tokens[2]).is_none());
+        assert!(matches!(lexer.decode_string(&tokens[4]), Some(Ok(( LiteralValue::Bytes(_), _ )))))
+    }
+
+    #[test]
+    fn decode_string_warnings_with_byte_offsets() {
+        let mut lexer = BorrowedTokenizer::borrowing("ø = b'\\d' b'\\q'\n", 4);
+        let tokens = lexer.tokenize().unwrap();
+        match lexer.decode_string(&tokens[2]) {
+            Some(Ok(( LiteralValue::Bytes(bytes), warnings ))) => {
+                assert_eq!(bytes, b"\\d\\q".to_vec());
+                assert_eq!(warnings, vec![ LiteralWarning::InvalidEscape(7, 9, 'd'), LiteralWarning::InvalidEscape(13, 15, 'q') ]);
+                assert_eq!(warnings[0].to_string(), "invalid escape sequence '\\d'")
+            },
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn decode_mixed_bytes_and_strings() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("b'a' 'b'\n"), 4);
+        let tokens = lexer.tokenize().unwrap();
+        match lexer.decode_string(&tokens[0]) {
+            Some(Err(error)) => assert_eq!(error.to_string(), "cannot mix bytes and nonbytes literals"),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn token_kind_of_symbols() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("def f(x): return x ** 2\n"), 4);
+        let tokens = lexer.tokenize().unwrap();
+        let kinds : Vec<TokenKind> = tokens.iter().map(|symbol| symbol.kind()).collect();
+        assert_eq!(kinds, vec![
+            TokenKind::PyDef, TokenKind::PyName, TokenKind::PyLeftParen, TokenKind::PyName, TokenKind::PyRightParen, TokenKind::PyColon,
+            TokenKind::PyReturn, TokenKind::PyName, TokenKind::PyPower, TokenKind::PyNumber, TokenKind::PyNewline, TokenKind::PyEof
+        ]);
+        assert_eq!(TokenKind::from(&*tokens[8]), TokenKind::PyPower)
+    }
+
+    #[test]
+    fn token_with_span_and_text() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("x = 1\nﬁ = 'a'  'b'\n"), 4);
+        let tokens = lexer.tokenize().unwrap();
+        let name = lexer.token(&tokens[4]);
+        assert_eq!(name.kind, TokenKind::PyName);
+        assert_eq!(&*name.text, "fi");
+        assert_eq!(( name.span.start.line, name.span.start.column, name.span.end.column ), ( 2, 0, 1 ));
+        let string = lexer.token(&tokens[6]);
+        assert_eq!(string.kind, TokenKind::PyString);
+        assert_eq!(&*string.text, "'a'  'b'");
+        assert_eq!(( string.span.start.line, string.span.start.column, string.span.end.column ), ( 2, 4, 12 ))
+    }
+
+    #[test]
+    fn tokens_compare_equal() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("x **= -1\n"), 4);
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(*tokens, vec![
+            Box::new(TokenSymbol::PyName(0, 1, Box::from("x"))),
+            Box::new(TokenSymbol::PyPowerAssign(2, 5)),
+            Box::new(TokenSymbol::PyMinus(6, 7)),
+            Box::new(TokenSymbol::PyNumber(7, 8, Box::from("1"))),
+            Box::new(TokenSymbol::PyNewline(8, 9)),
+            Box::new(TokenSymbol::PyEof(9, 9))
+        ]);
+        let unique : std::collections::HashSet<TokenSymbol> = tokens.iter().map(|symbol| *symbol.clone()).collect();
+        assert_eq!(unique.len(), 6)
+    }
+
+    #[test]
+    fn tokens_display_source_spelling() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("def f() -> 'a' 'b': return False\n"), 4);
+        let tokens = lexer.tokenize().unwrap();
+        let spellings : Vec<String> = tokens.iter().map(|symbol| symbol.to_string()).collect();
+        assert_eq!(spellings, vec![ "def", "f", "(", ")", "->", "'a' 'b'", ":", "return", "False", "\n", "" ])
+    }
+
+    #[test]
+    fn tokens_display_exact_source_text() {
+        let source = "x = ('a'\t\"b\"\r\n     r'c')\r\ny = 'd' \\\n'e'";
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from(source), 4);
+        let tokens = lexer.tokenize().unwrap();
+        let spellings : Vec<String> = tokens.iter().map(|symbol| symbol.to_string()).collect();
+        assert_eq!(spellings, vec![ "x", "=", "(", "'a'\t\"b\"\r\n     r'c'", ")", "\r\n", "y", "=", "'d' \\\n'e'", "", "" ])
+    }
+
+    #[test]
+    fn tokens_debug_as_cpython_tokenize() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("if x:\n    y **= \"it's\"\n"), 4);
+        let tokens = lexer.tokenize().unwrap();
+        let symbols : Vec<String> = tokens.iter().map(|symbol| format!("{:?}", symbol)).collect();
+        assert_eq!(symbols, vec![
+            "NAME 'if' 0-2", "NAME 'x' 3-4", "OP ':' 4-5", "NEWLINE '\\n' 5-6", "INDENT '' 6-10", "NAME 'y' 10-11",
+            "OP '**=' 12-15", "STRING '\"it\\'s\"' 16-22", "NEWLINE '\\n' 22-23", "DEDENT '' 23-23", "ENDMARKER '' 23-23"
+        ]);
+        let spans : Vec<String> = tokens.iter().map(|symbol| format!("{:?}", lexer.token(symbol))).collect();
+        assert_eq!(spans[4], "INDENT '    ' 2:0-2:4");
+        assert_eq!(spans[6], "OP '**=' 2:6-2:9");
+        assert_eq!(lexer.token(&tokens[7]).to_string(), "\"it's\"")
+    }
+
+    #[test]
+    fn token_info_of_multi_line_string() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("x = '''a\nb'''\n"), 4);
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(lexer.token_info(&tokens[2]), TokenInfo {
+            type_name: Box::from("STRING"),
+            string: Box::from("'''a\nb'''"),
+            start: ( 1, 4 ),
+            end: ( 2, 4 ),
+            line: Box::from("x = '''a\nb'''\n")
+        })
+    }
+
+    #[test]
+    fn classify_tokens() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("match x:\n    case [1]: y **= f'{x}' if not None else 2.0\n"), 4);
+        let tokens = lexer.tokenize().unwrap();
+        assert!(tokens[0].is_soft_keyword() && !tokens[0].is_keyword());
+        assert!(tokens[5].is_soft_keyword());
+        assert!(tokens[6].is_bracket_open() && tokens[6].is_operator() && !tokens[6].is_bracket_close());
+        assert!(tokens[8].is_bracket_close());
+        assert!(tokens[11].is_augmented_assign() && tokens[11].is_operator());
+        assert!(tokens[12].is_literal() && tokens[13].is_bracket_open());
+        assert!(tokens[17].is_keyword() && tokens[18].is_keyword() && !tokens[18].is_literal());
+        assert!(tokens[21].is_literal() && !tokens[22].is_operator());
+        assert!(!tokens[1].is_keyword() && !tokens[1].is_operator() && !tokens[1].is_augmented_assign())
+    }
+
+    #[test]
+    fn category_of_tokens() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("def f(a) -> int: return a @ 2 # c\n"), 4).with_comments(true);
+        let tokens = lexer.tokenize().unwrap();
+        let categories : Vec<TokenCategory> = tokens.iter().map(|symbol| symbol.category()).collect();
+        assert_eq!(categories, vec![
+            TokenCategory::Keyword, TokenCategory::Name, TokenCategory::Delimiter, TokenCategory::Name, TokenCategory::Delimiter,
+            TokenCategory::Delimiter, TokenCategory::Name, TokenCategory::Delimiter, TokenCategory::Keyword, TokenCategory::Name,
+            TokenCategory::Operator, TokenCategory::Number, TokenCategory::Comment, TokenCategory::Layout, TokenCategory::Layout
+        ])
+    }
+
+    #[test]
+    fn tokenize_keywords_for_python_version() {
+        let source = "async def f(): await x\nmatch = type = 1\n";
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from(source), 4).with_python_version(PythonVersion::new(3, 6));
+        let tokens = lexer.tokenize().unwrap();
+        match ( &*tokens[0], &*tokens[6], &*tokens[9], &*tokens[11] ) {
+            ( TokenSymbol::PyName(0, 5, _), TokenSymbol::PyName(15, 20, _), TokenSymbol::PyName(23, 28, _), TokenSymbol::PyName(31, 35, _) ) => assert!(true),
+            _ => assert!(false)
+        }
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from(source), 4).with_python_version(PythonVersion::PY311);
+        let tokens = lexer.tokenize().unwrap();
+        match ( &*tokens[0], &*tokens[6], &*tokens[9], &*tokens[11] ) {
+            ( TokenSymbol::PyAsync(0, 5), TokenSymbol::PyAwait(15, 20), TokenSymbol::PyMatch(23, 28), TokenSymbol::PyName(31, 35, _) ) => assert!(true),
+            _ => assert!(false)
+        }
+        assert!(lexer.is_soft_keyword("type", 0, 4).is_none() && lexer.is_soft_keyword("case", 0, 4).is_some());
+        assert_eq!(lexer.python_version().to_string(), "3.11")
+    }
+
+    #[test]
+    fn tokenize_fstring_as_string_before_python_3_12() {
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("'a' f'{x}' 'c'\n"), 4).with_python_version(PythonVersion::PY311);
+        let tokens = lexer.tokenize().unwrap();
+        assert_eq!(tokens.len(), 3);
+        match &*tokens[0] {
+            TokenSymbol::PyString(0, 14, parts) => assert_eq!(parts.len(), 3),
+            _ => assert!(false)
+        }
+    }
+
+    #[test]
+    fn tokenize_literal_forms_for_python_version() {
+        let cases = [
+            ( "1_000", PythonVersion::new(3, 5), "invalid decimal literal" ),
+            ( "0x_ff", PythonVersion::new(3, 5), "invalid hexadecimal literal" )
+        ];
+        for ( source, version, expected ) in cases {
+            let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from(source), 4).with_python_version(version);
+            match lexer.tokenize() {
+                Err(error) => assert_eq!(error.to_string(), expected),
+                _ => assert!(false)
+            }
+        }
+        let mut lexer : PythonCoreTokenizer = PythonCoreTokenizer::new(String::from("u'a'"), 4).with_python_version(PythonVersion::new(3, 2));
+        let tokens = lexer.tokenize().unwrap();
+        match ( &*tokens[0], &*tokens[1] ) {
+            ( TokenSymbol::PyName(0, 1, _), TokenSymbol::PyString(1, 4, _) ) => assert!(true),
+            _ => assert!(false)
+        }
+    }
 }
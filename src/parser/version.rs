@@ -0,0 +1,99 @@
+use std::fmt;
+
+// Versions of Python //////////////////////////////////////////////////////////////////////////////
+
+// Version of Python source is written for, which decides keywords, f-string tokens and literal forms
+// accepted by tokenizer. Versions are ordered, so that features can be checked by comparing versions.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct PythonVersion {
+    pub major: u8,
+    pub minor: u8
+}
+
+impl PythonVersion {
+    pub const PY36: PythonVersion = PythonVersion::new(3, 6);
+    pub const PY37: PythonVersion = PythonVersion::new(3, 7);
+    pub const PY38: PythonVersion = PythonVersion::new(3, 8);
+    pub const PY39: PythonVersion = PythonVersion::new(3, 9);
+    pub const PY310: PythonVersion = PythonVersion::new(3, 10);
+    pub const PY311: PythonVersion = PythonVersion::new(3, 11);
+    pub const PY312: PythonVersion = PythonVersion::new(3, 12);
+    pub const PY313: PythonVersion = PythonVersion::new(3, 13);
+
+    // Newest version known, used by tokenizer unless another version is chosen.
+    pub const LATEST: PythonVersion = PythonVersion::PY313;
+
+    pub const fn new(major: u8, minor: u8) -> Self {
+        PythonVersion { major, minor }
+    }
+
+    // 'u' prefix of strings from PEP 414, and 'rb' as well as 'br' for raw bytes.
+    pub(crate) fn has_unicode_prefix(self) -> bool {
+        self >= PythonVersion::new(3, 3)
+    }
+
+    // Formatted string literals from PEP 498.
+    pub(crate) fn has_fstrings(self) -> bool {
+        self >= PythonVersion::PY36
+    }
+
+    // Underscores in numeric literals from PEP 515.
+    pub(crate) fn has_number_underscores(self) -> bool {
+        self >= PythonVersion::PY36
+    }
+
+    // 'async' and 'await' are reserved keywords, and names in earlier versions.
+    pub(crate) fn has_async_keywords(self) -> bool {
+        self >= PythonVersion::PY37
+    }
+
+    // 'match', 'case' and '_' soft keywords of structural pattern matching from PEP 634.
+    pub(crate) fn has_match_statement(self) -> bool {
+        self >= PythonVersion::PY310
+    }
+
+    // 'type' soft keyword of type alias statements from PEP 695.
+    pub(crate) fn has_type_statement(self) -> bool {
+        self >= PythonVersion::PY312
+    }
+
+    // F-strings scanned into start, middle and end tokens from PEP 701, and whole as strings earlier.
+    pub(crate) fn has_fstring_tokens(self) -> bool {
+        self >= PythonVersion::PY312
+    }
+}
+
+impl Default for PythonVersion {
+    fn default() -> Self {
+        PythonVersion::LATEST
+    }
+}
+
+impl fmt::Display for PythonVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn order_and_display_of_versions() {
+        assert!(PythonVersion::PY37 < PythonVersion::PY310 && PythonVersion::new(3, 9) == PythonVersion::PY39);
+        assert_eq!(PythonVersion::default(), PythonVersion::LATEST);
+        assert_eq!(PythonVersion::PY312.to_string(), "3.12")
+    }
+
+    #[test]
+    fn features_of_versions() {
+        assert!(!PythonVersion::PY36.has_async_keywords() && PythonVersion::PY37.has_async_keywords());
+        assert!(!PythonVersion::PY39.has_match_statement() && PythonVersion::PY310.has_match_statement());
+        assert!(!PythonVersion::PY311.has_type_statement() && PythonVersion::PY312.has_type_statement());
+        assert!(!PythonVersion::PY311.has_fstring_tokens() && PythonVersion::PY313.has_fstring_tokens());
+        assert!(!PythonVersion::new(3, 5).has_fstrings() && !PythonVersion::new(3, 5).has_number_underscores())
+    }
+}